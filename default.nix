@@ -9,9 +9,10 @@ rustPlatform.buildRustPackage {
   cargoBuildFlags = [ "--no-default-features" ];
   cargoTestFlags = [ "--no-default-features" ];
 
-  # Install desktop entry, icons, and D-Bus activation service into $out
+  # Install desktop entry, icons, D-Bus activation service and systemd user unit into $out
   postInstall = ''
-    install -Dm0644 assets/muxie.desktop "$out/share/applications/muxie.desktop"
+    install -Dm0644 assets/xyz.adf.Muxie.desktop "$out/share/applications/xyz.adf.Muxie.desktop"
+    install -Dm0644 resources/packaging/shared/muxie.desktop "$out/share/applications/muxie.desktop"
     for s in 32 48 64 96 128 256; do
       install -Dm0644 assets/icons/''${s}x''${s}/muxie.png "$out/share/icons/hicolor/''${s}x''${s}/apps/muxie.png"
     done
@@ -20,6 +21,10 @@ rustPlatform.buildRustPackage {
       "$out/share/dbus-1/services/xyz.adf.Muxie.service"
     substituteInPlace "$out/share/dbus-1/services/xyz.adf.Muxie.service" \
       --replace /usr/bin/muxie "$out/bin/muxie"
+    install -Dm0644 resources/packaging/shared/muxie.service \
+      "$out/lib/systemd/user/muxie.service"
+    substituteInPlace "$out/lib/systemd/user/muxie.service" \
+      --replace /usr/bin/muxie "$out/bin/muxie"
   '';
 
   meta = with lib; {
@@ -13,7 +13,7 @@ if [ ! -e result ]; then
 fi
 
 echo "==> Validating installed assets under result/"
-test -f result/share/applications/muxie.desktop || { echo "Missing desktop entry" >&2; exit 3; }
+test -f result/share/applications/xyz.adf.Muxie.desktop || { echo "Missing desktop entry" >&2; exit 3; }
 for s in 32 48 64 96 128 256; do
   test -f "result/share/icons/hicolor/${s}x${s}/apps/muxie.png" || {
     echo "Missing icon ${s}x${s}" >&2; exit 4;
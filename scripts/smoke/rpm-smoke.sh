@@ -61,6 +61,13 @@ if ! /usr/bin/muxie --help >/dev/null 2>&1; then
 fi
 echo "==> Binary run check passed"
 
+echo "==> Checking systemd user unit"
+if ! grep -q '^SystemdService=muxie.service$' /usr/share/dbus-1/services/xyz.adf.Muxie.service \
+  || ! test -f /usr/lib/systemd/user/muxie.service; then
+  echo "systemd user unit or D-Bus SystemdService= missing" >&2
+  exit 8
+fi
+
 echo "==> Checking that install/uninstall subcommands are not available (packaged build)"
 if /usr/bin/muxie install --help >/dev/null 2>&1; then
   echo "install subcommand unexpectedly available in packaged build" >&2
@@ -1,15 +1,43 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Browser {
+    /// Name used in patterns and shown in the selection dialog.
     pub name: String,
     pub executable: String,
 
+    /// Arguments; `%u` or `%U` is replaced by the URL, which is appended otherwise.
     #[serde(default)]
     pub args: Vec<String>,
+
+    /// Set in a system-wide configuration file so user configuration cannot redefine
+    /// the browser. Has no effect in the user's own files.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
 }
 
 impl Browser {
+    /// Command line used to open `url`: the executable followed by its arguments, with
+    /// `%u`/`%U` replaced by the URL (or the URL appended if neither is present).
+    pub fn command_line(&self, url: &str) -> Vec<String> {
+        let mut argv = vec![self.executable.clone()];
+        let mut url_arg_found = false;
+        for arg in &self.args {
+            match arg.as_str() {
+                "%u" | "%U" => {
+                    url_arg_found = true;
+                    argv.push(url.to_string());
+                }
+                _ => argv.push(arg.clone()),
+            }
+        }
+        if !url_arg_found {
+            argv.push(url.to_string());
+        }
+        argv
+    }
+
     pub fn from_desktop_entry(entry: &freedesktop_desktop_entry::DesktopEntry) -> Option<Browser> {
         let (name, exec, mime_type) = match (entry.name(None), entry.exec(), entry.mime_type()) {
             (Some(name), Some(exec), Some(mime)) => (name.to_string(), exec, mime),
@@ -28,6 +56,31 @@ impl Browser {
             name,
             executable,
             args,
+            locked: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_substitutes_url() {
+        let b = Browser {
+            name: "F".into(),
+            executable: "firefox".into(),
+            args: vec!["-P".into(), "work".into(), "%u".into()],
+            locked: false,
+        };
+        assert_eq!(
+            b.command_line("https://example.com"),
+            ["firefox", "-P", "work", "https://example.com"]
+        );
+        let b = Browser { args: vec![], ..b };
+        assert_eq!(
+            b.command_line("https://example.com"),
+            ["firefox", "https://example.com"]
+        );
+    }
+}
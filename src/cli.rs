@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -12,6 +12,11 @@ pub struct Cli {
     #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
     pub verbose: u8,
 
+    /// Configuration file to use instead of ~/.config/muxie/muxie.toml (also MUXIE_CONFIG).
+    /// A daemon started with another file gets its own bus name and socket
+    #[arg(long = "config", global = true, value_name = "PATH")]
+    pub config: Option<std::path::PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -23,7 +28,20 @@ pub enum Commands {
     Install {},
 
     /// Open URL
-    Open { url: Option<String> },
+    Open {
+        url: Option<String>,
+
+        /// Route the URL in this process instead of asking the daemon
+        #[arg(long = "no-daemon", conflicts_with = "daemon_only")]
+        no_daemon: bool,
+
+        /// Fail instead of routing in this process when the daemon is unavailable
+        #[arg(long = "daemon-only")]
+        daemon_only: bool,
+    },
+
+    /// Show how a URL would be routed, without opening it
+    Route { url: String },
 
     /// Daemon-related commands
     Daemon {
@@ -52,6 +70,107 @@ pub enum Commands {
         #[command(subcommand)]
         command: ConfigCommands,
     },
+
+    /// Show recently opened URLs (requires `[history] enabled = true`)
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommands>,
+
+        /// Only show entries newer than a duration (e.g. 12h, 7d) or a date (2025-09-01)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only show URLs opened with this browser
+        #[arg(long)]
+        browser: Option<String>,
+
+        /// Print entries as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show how often each pattern matched and which patterns never did
+    Stats {
+        /// Only count opens from the last N days (0 = all recorded history)
+        #[arg(long, default_value_t = 30)]
+        days: u32,
+
+        /// Print statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List, add or remove browsers in the configuration file
+    Browser {
+        #[command(subcommand)]
+        command: BrowserCommands,
+    },
+
+    /// List, add, remove or reorder routing rules in the configuration file
+    Rule {
+        #[command(subcommand)]
+        command: RuleCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BrowserCommands {
+    /// List configured browsers
+    List {
+        /// List the browsers installed on the system instead
+        #[arg(long)]
+        installed: bool,
+    },
+
+    /// Add a browser. Without --executable, it is taken from the installed browsers by name
+    Add {
+        name: String,
+
+        /// Command to run
+        #[arg(long)]
+        executable: Option<String>,
+
+        /// Argument for the command (repeatable); `%u` is replaced by the URL
+        #[arg(long = "arg", allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Remove a browser; refused while rules still use it
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum RuleCommands {
+    /// List rules in routing order, with their index in the configuration file
+    List {},
+
+    /// Add a rule sending URLs that match PATTERN to BROWSERS, in preference order
+    Add {
+        pattern: String,
+
+        #[arg(required = true)]
+        browsers: Vec<String>,
+
+        /// Insert before the rule with this index instead of appending
+        #[arg(long)]
+        at: Option<usize>,
+
+        /// Try browsers that are already running first
+        #[arg(long = "prefer-running")]
+        prefer_running: bool,
+    },
+
+    /// Remove the rule with this index
+    Remove { index: usize },
+
+    /// Move the rule at FROM so that it gets index TO
+    Move { from: usize, to: usize },
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommands {
+    /// Delete all recorded history
+    Clear {},
 }
 
 #[derive(Subcommand)]
@@ -60,7 +179,39 @@ pub enum ConfigCommands {
     Create {},
 
     /// Validate the configuration file (strict mode)
-    Validate {},
+    Validate {
+        /// Exit with an error status on warnings too
+        #[arg(long = "deny-warnings")]
+        deny_warnings: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ValidateFormat::Human)]
+        format: ValidateFormat,
+    },
+
+    /// Show the effective configuration after merging system-wide and included files
+    Show {},
+
+    /// Print a JSON Schema of the configuration file, for editor completion and validation
+    Schema {},
+
+    /// Upgrade the configuration file (or the YAML file of muxie before 0.2.0) to the
+    /// current format. Prints the result unless --write is given
+    Migrate {
+        /// Replace the file, keeping the original as a .bak file
+        #[arg(long)]
+        write: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ValidateFormat {
+    /// Human-readable list of issues
+    Human,
+    /// JSON object with one entry per issue, including line and column
+    Json,
+    /// SARIF 2.1.0 log for code scanning tools
+    Sarif,
 }
 
 #[derive(Subcommand)]
@@ -73,6 +224,12 @@ pub enum DaemonCommands {
 
     /// Reload the daemon configuration
     Reload {},
+
+    /// Start the daemon with the graphical session (systemd user service)
+    Enable {},
+
+    /// Stop the daemon and no longer start it with the session
+    Disable {},
 }
 
 #[cfg(test)]
@@ -86,11 +243,171 @@ mod tests {
         assert!(!cli.no_notify);
         assert_eq!(cli.verbose, 0);
         match cli.command {
-            Commands::Open { url } => assert_eq!(url.as_deref(), Some("https://example.com")),
+            Commands::Open { url, .. } => assert_eq!(url.as_deref(), Some("https://example.com")),
             _ => panic!("expected Open command"),
         }
     }
 
+    #[test]
+    fn parse_open_daemon_modes() {
+        let cli = Cli::parse_from(["muxie", "open", "--no-daemon", "https://x"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Open {
+                no_daemon: true,
+                daemon_only: false,
+                ..
+            }
+        ));
+        let cli = Cli::parse_from(["muxie", "open", "--daemon-only", "https://x"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Open {
+                no_daemon: false,
+                daemon_only: true,
+                ..
+            }
+        ));
+        assert!(
+            Cli::try_parse_from(["muxie", "open", "--no-daemon", "--daemon-only", "https://x"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_history() {
+        let cli = Cli::parse_from(["muxie", "history", "--since", "7d", "--browser", "Work"]);
+        match cli.command {
+            Commands::History {
+                command: None,
+                since,
+                browser,
+                json: false,
+            } => {
+                assert_eq!(since.as_deref(), Some("7d"));
+                assert_eq!(browser.as_deref(), Some("Work"));
+            }
+            _ => panic!("expected History command"),
+        }
+        let cli = Cli::parse_from(["muxie", "history", "clear"]);
+        assert!(matches!(
+            cli.command,
+            Commands::History {
+                command: Some(HistoryCommands::Clear {}),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_stats() {
+        let cli = Cli::parse_from(["muxie", "stats"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Stats {
+                days: 30,
+                json: false
+            }
+        ));
+        let cli = Cli::parse_from(["muxie", "stats", "--days", "0", "--json"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Stats {
+                days: 0,
+                json: true
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_validate_format() {
+        let cli = Cli::parse_from(["muxie", "config", "validate", "--format", "sarif"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Config {
+                command: ConfigCommands::Validate {
+                    format: ValidateFormat::Sarif,
+                    deny_warnings: false
+                }
+            }
+        ));
+        assert!(Cli::try_parse_from(["muxie", "config", "validate", "--format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn parse_global_config() {
+        let cli = Cli::parse_from(["muxie", "daemon", "run", "--config", "candidate.toml"]);
+        assert_eq!(
+            cli.config.as_deref(),
+            Some(std::path::Path::new("candidate.toml"))
+        );
+        assert!(matches!(
+            cli.command,
+            Commands::Daemon {
+                command: DaemonCommands::Run {}
+            }
+        ));
+        let cli = Cli::parse_from(["muxie", "--config", "/tmp/a.toml", "route", "x"]);
+        assert!(cli.config.is_some());
+    }
+
+    #[test]
+    fn parse_edit_commands() {
+        let cli = Cli::parse_from([
+            "muxie",
+            "browser",
+            "add",
+            "Work",
+            "--executable",
+            "firefox",
+            "--arg",
+            "-P",
+            "--arg",
+            "work",
+        ]);
+        match cli.command {
+            Commands::Browser {
+                command:
+                    BrowserCommands::Add {
+                        name,
+                        executable,
+                        args,
+                    },
+            } => {
+                assert_eq!(name, "Work");
+                assert_eq!(executable.as_deref(), Some("firefox"));
+                assert_eq!(args, ["-P", "work"]);
+            }
+            _ => panic!("expected browser add"),
+        }
+        let cli = Cli::parse_from([
+            "muxie", "rule", "add", "*.corp/*", "Work", "Firefox", "--at", "0",
+        ]);
+        assert!(matches!(
+            cli.command,
+            Commands::Rule {
+                command: RuleCommands::Add { at: Some(0), ref browsers, .. }
+            } if browsers.len() == 2
+        ));
+        assert!(Cli::try_parse_from(["muxie", "rule", "add", "example.com"]).is_err());
+        assert!(matches!(
+            Cli::parse_from(["muxie", "rule", "move", "3", "0"]).command,
+            Commands::Rule {
+                command: RuleCommands::Move { from: 3, to: 0 }
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_route() {
+        let cli = Cli::parse_from(["muxie", "-v", "route", "https://example.com"]);
+        assert_eq!(cli.verbose, 1);
+        match cli.command {
+            Commands::Route { url } => assert_eq!(url, "https://example.com"),
+            _ => panic!("expected Route command"),
+        }
+    }
+
     #[test]
     fn parse_daemon_run() {
         let cli = Cli::parse_from(["muxie", "daemon", "run"]);
@@ -115,6 +432,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_daemon_enable_disable() {
+        let cli = Cli::parse_from(["muxie", "daemon", "enable"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Daemon {
+                command: DaemonCommands::Enable {}
+            }
+        ));
+        let cli = Cli::parse_from(["muxie", "daemon", "disable"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Daemon {
+                command: DaemonCommands::Disable {}
+            }
+        ));
+    }
+
     #[test]
     fn parse_daemon_reload() {
         let cli = Cli::parse_from(["muxie", "daemon", "reload"]);
@@ -145,7 +480,7 @@ mod tests {
         assert!(cli.no_notify);
         assert_eq!(cli.verbose, 2);
         match cli.command {
-            Commands::Open { url } => assert_eq!(url.as_deref(), Some("https://x")),
+            Commands::Open { url, .. } => assert_eq!(url.as_deref(), Some("https://x")),
             _ => panic!("expected Open command"),
         }
     }
@@ -1,21 +1,73 @@
-use crate::daemon::{DBUS_INTERFACE, DBUS_METHOD_OPEN_URL_FD, DBUS_PATH, DBUS_SERVICE};
+use crate::daemon::{
+    DBUS_ERROR_CANCELED, DBUS_INTERFACE, DBUS_METHOD_OPEN_URL_FD, DBUS_PATH, bus_name,
+};
+use crate::socket::{Request, Response};
 use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// Client interface to the Muxie daemon.
 pub trait MuxieClient {
     fn open_url(&self, url: &str) -> Result<()>;
 }
 
+/// The daemon did not answer within the configured timeout.
+#[derive(Debug)]
+pub(crate) struct Timeout(pub Duration);
+
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "daemon did not respond within {}s", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/// Errors after which a call is retried once: the daemon was not running yet, failed to
+/// start, or exited (e.g. on idle timeout) before replying.
+const RETRY_ERRORS: &[&str] = &[
+    "org.freedesktop.DBus.Error.ServiceUnknown",
+    "org.freedesktop.DBus.Error.NameHasNoOwner",
+    "org.freedesktop.DBus.Error.NoReply",
+    "org.freedesktop.DBus.Error.Spawn.",
+];
+
+fn is_retryable(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<zbus::Error>() {
+        Some(zbus::Error::MethodError(name, _, _)) => {
+            RETRY_ERRORS.iter().any(|r| name.as_str().starts_with(r))
+        }
+        _ => false,
+    }
+}
+
+fn is_canceled(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<zbus::Error>(),
+        Some(zbus::Error::MethodError(name, _, _)) if name.as_str() == DBUS_ERROR_CANCELED
+    )
+}
+
 /// zbus-based client implementation.
 pub struct ZbusClient {
     conn: zbus::blocking::Connection,
+    timeout: Option<Duration>,
 }
 
 impl ZbusClient {
     pub(crate) fn new() -> Result<Self> {
         let conn =
             zbus::blocking::Connection::session().context("Failed to connect to session D-Bus")?;
-        Ok(Self { conn })
+        Ok(Self {
+            conn,
+            timeout: None,
+        })
+    }
+
+    /// Give up on daemon calls after `timeout`; `None` waits indefinitely.
+    pub(crate) fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
     }
 
     /// Check if the daemon service name currently has an owner without activating it.
@@ -24,7 +76,8 @@ impl ZbusClient {
             zbus::blocking::Connection::session().context("Failed to connect to session D-Bus")?;
         let proxy =
             zbus::blocking::fdo::DBusProxy::new(&conn).context("Failed to create DBusProxy")?;
-        let name = zbus_names::BusName::try_from(DBUS_SERVICE)
+        let name = bus_name();
+        let name = zbus_names::BusName::try_from(name.as_str())
             .context("Invalid service name for D-Bus")?;
         let has = proxy
             .name_has_owner(name)
@@ -36,7 +89,7 @@ impl ZbusClient {
         use zbus::blocking::Proxy;
         let conn =
             zbus::blocking::Connection::session().context("Failed to connect to session D-Bus")?;
-        let proxy = Proxy::new(&conn, DBUS_SERVICE, DBUS_PATH, DBUS_INTERFACE)
+        let proxy = Proxy::new(&conn, bus_name(), DBUS_PATH, DBUS_INTERFACE)
             .context("Failed to create daemon proxy")?;
         let res: bool = proxy
             .call_method(crate::daemon::DBUS_METHOD_RELOAD, &())
@@ -44,53 +97,195 @@ impl ZbusClient {
             .context("Failed to call ReloadConfig on daemon")?;
         Ok(res)
     }
+
+    /// Call a daemon method, retrying once after activating the service if the daemon was
+    /// not available. `body` is invoked per attempt since it may carry file descriptors.
+    fn call<B, F>(&self, method: &str, body: F) -> Result<zbus::Message>
+    where
+        F: Fn() -> Result<B>,
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        match self.call_once(method, &body()?) {
+            Err(e) if is_retryable(&e) => {
+                self.start_service();
+                self.call_once(method, &body()?)
+            }
+            res => res,
+        }
+    }
+
+    fn call_once<B>(&self, method: &str, body: &B) -> Result<zbus::Message>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        let conn = self.conn.inner();
+        let name = bus_name();
+        async_io::block_on(async {
+            let call = async {
+                conn.call_method(
+                    Some(name.as_str()),
+                    DBUS_PATH,
+                    Some(DBUS_INTERFACE),
+                    method,
+                    body,
+                )
+                .await
+                .map_err(anyhow::Error::from)
+            };
+            match self.timeout {
+                None => call.await,
+                Some(timeout) => {
+                    futures_lite::future::or(call, async {
+                        async_io::Timer::after(timeout).await;
+                        Err(Timeout(timeout).into())
+                    })
+                    .await
+                }
+            }
+        })
+    }
+
+    /// Ask the bus to start the daemon (best effort).
+    fn start_service(&self) {
+        if let Ok(proxy) = zbus::blocking::fdo::DBusProxy::new(&self.conn)
+            && let Ok(name) = zbus_names::WellKnownName::try_from(bus_name())
+        {
+            let _ = proxy.start_service_by_name(name, 0);
+        }
+    }
 }
 
-impl MuxieClient for ZbusClient {
-    fn open_url(&self, url: &str) -> Result<()> {
-        use std::io::Write;
-        use std::os::unix::io::{FromRawFd, RawFd};
-        use zbus::blocking::Proxy;
+/// Client for the daemon's Unix socket (`[daemon] socket = true`), used when there is no
+/// session bus.
+pub struct SocketClient {
+    path: PathBuf,
+    timeout: Option<Duration>,
+}
 
-        // Create a pipe and write URL to the write end
-        let mut fds = [0 as libc::c_int; 2];
-        let rc = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
-        if rc != 0 {
-            let os_err = std::io::Error::last_os_error();
-            return Err(anyhow::anyhow!("pipe2 failed: {}", os_err));
+impl SocketClient {
+    pub(crate) fn new() -> Result<Self> {
+        let path = crate::paths::socket_path().context("XDG_RUNTIME_DIR is not set")?;
+        Ok(Self::at(path))
+    }
+
+    fn at(path: PathBuf) -> Self {
+        SocketClient {
+            path,
+            timeout: None,
         }
-        let rfd: RawFd = fds[0];
-        let wfd: RawFd = fds[1];
-        // SAFETY: we immediately wrap raw fd and close when dropped
-        let mut wfile = unsafe { std::fs::File::from_raw_fd(wfd) };
-        wfile.write_all(url.as_bytes())?;
-        // Drop write end to signal EOF to the daemon
-        drop(wfile);
-
-        // Wrap read end as OwnedFd for zbus
-        // SAFETY: rfd comes from a new pipe we created above
-        let std_owned = unsafe { std::os::fd::OwnedFd::from_raw_fd(rfd) };
-        let zfd = zbus::zvariant::OwnedFd::from(std_owned);
-
-        let proxy = Proxy::new(&self.conn, DBUS_SERVICE, DBUS_PATH, DBUS_INTERFACE)
-            .context("Failed to create daemon proxy")?;
-        match proxy.call_method(DBUS_METHOD_OPEN_URL_FD, &(zfd)) {
-            Ok(_) => (),
-            Err(e) => {
-                let es = e.to_string();
-                if es.contains(crate::open::CANCELED_ERR_MARKER) {
-                    anyhow::bail!(crate::open::CANCELED_ERR_MARKER);
-                }
-                return Err(anyhow::Error::new(e).context("Failed to call OpenUrlFd on daemon"));
+    }
+
+    /// Give up on requests after `timeout`; `None` waits indefinitely.
+    pub(crate) fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn request(&self, req: &Request) -> Result<Response> {
+        use std::io::BufRead;
+        use std::os::unix::net::UnixStream;
+        let stream = UnixStream::connect(&self.path)
+            .with_context(|| format!("Failed to connect to {}", self.path.display()))?;
+        stream.set_read_timeout(self.timeout)?;
+        let mut writer = stream.try_clone()?;
+        crate::socket::write_line(&mut writer, req)?;
+        let mut line = String::new();
+        match std::io::BufReader::new(stream).read_line(&mut line) {
+            Ok(0) => anyhow::bail!("Daemon closed the connection"),
+            Ok(_) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(Timeout(self.timeout.unwrap_or_default()).into());
             }
-        };
-        Ok(())
+            Err(e) => return Err(e).context("Failed to read daemon response"),
+        }
+        serde_json::from_str(&line).context("Invalid daemon response")
+    }
+
+    /// Like [`ZbusClient::reload`]: `false` if the new configuration could not be read.
+    pub(crate) fn reload(&self) -> Result<bool> {
+        Ok(self.request(&Request::Reload)?.ok)
+    }
+
+    pub(crate) fn status(&self) -> Result<crate::socket::Status> {
+        self.request(&Request::Status)?
+            .status
+            .context("Daemon did not report its status")
+    }
+}
+
+impl MuxieClient for SocketClient {
+    fn open_url(&self, url: &str) -> Result<()> {
+        let resp = self.request(&Request::Open {
+            url: url.to_string(),
+        })?;
+        match resp.error.as_deref() {
+            _ if resp.ok => Ok(()),
+            Some(crate::socket::ERROR_CANCELED) => Err(crate::open::Canceled.into()),
+            _ => anyhow::bail!(
+                "Daemon failed to open URL: {}",
+                resp.message.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Connect to the daemon over D-Bus, or over its Unix socket when there is no session bus.
+pub(crate) fn connect(timeout: Option<Duration>) -> Result<Box<dyn MuxieClient>> {
+    match ZbusClient::new() {
+        Ok(client) => Ok(Box::new(client.with_timeout(timeout))),
+        Err(err) => match SocketClient::new() {
+            Ok(client) if client.path.exists() => Ok(Box::new(client.with_timeout(timeout))),
+            _ => Err(err),
+        },
+    }
+}
+
+/// Pass the URL through a pipe so it does not show up in D-Bus monitors' argument lists.
+fn url_pipe(url: &str) -> Result<zbus::zvariant::OwnedFd> {
+    use std::io::Write;
+    use std::os::unix::io::{FromRawFd, RawFd};
+
+    // Create a pipe and write URL to the write end
+    let mut fds = [0 as libc::c_int; 2];
+    let rc = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
+    if rc != 0 {
+        let os_err = std::io::Error::last_os_error();
+        return Err(anyhow::anyhow!("pipe2 failed: {}", os_err));
+    }
+    let rfd: RawFd = fds[0];
+    let wfd: RawFd = fds[1];
+    // SAFETY: we immediately wrap raw fd and close when dropped
+    let mut wfile = unsafe { std::fs::File::from_raw_fd(wfd) };
+    wfile.write_all(url.as_bytes())?;
+    // Drop write end to signal EOF to the daemon
+    drop(wfile);
+
+    // Wrap read end as OwnedFd for zbus
+    // SAFETY: rfd comes from a new pipe we created above
+    let std_owned = unsafe { std::os::fd::OwnedFd::from_raw_fd(rfd) };
+    Ok(zbus::zvariant::OwnedFd::from(std_owned))
+}
+
+impl MuxieClient for ZbusClient {
+    fn open_url(&self, url: &str) -> Result<()> {
+        match self.call(DBUS_METHOD_OPEN_URL_FD, || url_pipe(url).map(|fd| (fd,))) {
+            Ok(_) => Ok(()),
+            Err(e) if is_canceled(&e) => Err(crate::open::Canceled.into()),
+            Err(e) if e.downcast_ref::<Timeout>().is_some() => Err(e),
+            Err(e) => Err(e.context("Failed to call OpenUrlFd on daemon")),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::TempDir;
 
     struct OkClient;
     impl MuxieClient for OkClient {
@@ -141,4 +336,62 @@ mod tests {
         assert!(res.is_ok());
         assert!(fallback_called);
     }
+
+    fn method_error(name: &str) -> anyhow::Error {
+        let msg = zbus::message::Message::method_call("/", "Ping")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        zbus::Error::MethodError(
+            zbus_names::OwnedErrorName::try_from(name).unwrap(),
+            None,
+            msg,
+        )
+        .into()
+    }
+
+    #[test]
+    fn classifies_daemon_errors() {
+        assert!(is_retryable(&method_error(
+            "org.freedesktop.DBus.Error.ServiceUnknown"
+        )));
+        assert!(is_retryable(&method_error(
+            "org.freedesktop.DBus.Error.Spawn.ChildExited"
+        )));
+        assert!(!is_retryable(&method_error(DBUS_ERROR_CANCELED)));
+        assert!(is_canceled(&method_error(DBUS_ERROR_CANCELED)));
+        assert!(!is_retryable(&Timeout(Duration::from_secs(1)).into()));
+    }
+
+    struct CancelHandler;
+
+    impl crate::socket::Handler for CancelHandler {
+        fn handle(&self, req: Request, _peer_pid: Option<u32>) -> Response {
+            match req {
+                Request::Open { url } if url.contains("cancel") => {
+                    Response::error(crate::socket::ERROR_CANCELED, "Operation canceled by user")
+                }
+                Request::Open { .. } => Response::success(),
+                _ => Response::error(crate::socket::ERROR_FAILED, "unsupported"),
+            }
+        }
+    }
+
+    #[test]
+    fn socket_client_maps_responses() {
+        let dir = TempDir::new("client");
+        let path = dir.join("muxie.sock");
+        let server = crate::socket::SocketServer::bind(&path).unwrap();
+        server.spawn(std::sync::Arc::new(CancelHandler)).unwrap();
+
+        let client = SocketClient::at(path).with_timeout(Some(Duration::from_secs(5)));
+        assert!(client.open_url("https://example.com").is_ok());
+        let err = client.open_url("https://example.com/cancel").unwrap_err();
+        assert!(err.downcast_ref::<crate::open::Canceled>().is_some());
+        assert!(!client.reload().unwrap());
+        assert!(client.status().is_err());
+
+        drop(server);
+        assert!(client.open_url("https://example.com").is_err());
+    }
 }
@@ -0,0 +1,445 @@
+use crate::config::{NetworkCondition, PatternEntry, When};
+use crate::desktop::DesktopProbe;
+use crate::network::NetworkProbe;
+use crate::open::RouteContext;
+use crate::pattern::Pattern;
+use crate::process::SourceApp;
+use chrono::{Datelike, NaiveDateTime, Timelike, Weekday};
+
+/// Why a pattern entry did not apply to a URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Mismatch {
+    Url,
+    Source,
+    Schedule,
+    /// Network condition not met; carries a human-readable reason.
+    Network(String),
+    /// `env` or `workspace` condition not met; carries a human-readable reason.
+    Desktop(String),
+}
+
+impl Mismatch {
+    /// Whether the URL itself matched and only a `when`/`network`/`env`/`workspace` condition failed,
+    /// i.e. the pattern's `fallback` browsers apply.
+    pub(crate) fn is_condition(&self) -> bool {
+        matches!(
+            self,
+            Mismatch::Schedule | Mismatch::Network(_) | Mismatch::Desktop(_)
+        )
+    }
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Url => f.write_str("URL does not match"),
+            Mismatch::Source => f.write_str("source application does not match"),
+            Mismatch::Schedule => f.write_str("outside of 'when' schedule"),
+            Mismatch::Network(reason) => write!(f, "network condition not met: {reason}"),
+            Mismatch::Desktop(reason) => write!(f, "desktop condition not met: {reason}"),
+        }
+    }
+}
+
+/// Evaluate a pattern entry against a URL and the routing context.
+pub(crate) fn evaluate(pat: &PatternEntry, ctx: &RouteContext, url: &str) -> Result<(), Mismatch> {
+    if !pat.pattern.matches(url) {
+        return Err(Mismatch::Url);
+    }
+    if !source_matches(&pat.source, ctx.source.as_ref()) {
+        return Err(Mismatch::Source);
+    }
+    if let Some(when) = &pat.when
+        && !when_matches(when, ctx.now)
+    {
+        return Err(Mismatch::Schedule);
+    }
+    if let Some(net) = &pat.network {
+        network_matches(net, ctx.network.as_ref()).map_err(Mismatch::Network)?;
+    }
+    desktop_matches(pat, ctx.desktop.as_ref()).map_err(Mismatch::Desktop)?;
+    Ok(())
+}
+
+/// Check a pattern's `env` and `workspace` conditions against the caller's desktop context.
+pub(crate) fn desktop_matches(pat: &PatternEntry, probe: &dyn DesktopProbe) -> Result<(), String> {
+    for (name, wanted) in &pat.env {
+        match probe.env_var(name) {
+            Some(value) if value_matches(wanted, &value) => {}
+            Some(value) => return Err(format!("{name} is '{value}'")),
+            None => return Err(format!("{name} is not set")),
+        }
+    }
+    if !pat.workspace.is_empty() {
+        match probe.active_workspace() {
+            Some(ws) if pat.workspace.iter().any(|w| value_matches(w, &ws)) => {}
+            Some(ws) => return Err(format!("workspace is '{ws}'")),
+            None => return Err("active workspace is unknown".to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Case-sensitive comparison with optional `*` wildcards.
+fn value_matches(matcher: &str, value: &str) -> bool {
+    if matcher.contains('*') {
+        wildflower::Pattern::new(matcher.replace('?', r"\?").as_str()).matches(value)
+    } else {
+        matcher == value
+    }
+}
+
+/// Check a `network` condition; on failure returns a reason suitable for notifications.
+pub(crate) fn network_matches(
+    cond: &NetworkCondition,
+    probe: &dyn NetworkProbe,
+) -> Result<(), String> {
+    let any_of = |wanted: &[String], have: &[String]| {
+        wanted
+            .iter()
+            .any(|w| have.iter().any(|h| name_matches(w.trim(), h)))
+    };
+    if !cond.interface.is_empty() && !any_of(&cond.interface, &probe.interfaces_up()) {
+        return Err(format!("interface {} is not up", cond.interface.join("/")));
+    }
+    if !cond.default_route.is_empty()
+        && !any_of(&cond.default_route, &probe.default_route_interfaces())
+    {
+        return Err(format!(
+            "default route is not via {}",
+            cond.default_route.join("/")
+        ));
+    }
+    if !cond.ssid.is_empty() {
+        // SSIDs are case-sensitive
+        let ssids = probe.wifi_ssids();
+        let connected = cond
+            .ssid
+            .iter()
+            .any(|w| ssids.iter().any(|h| value_matches(w, h)));
+        if !connected {
+            return Err(format!("not connected to Wi-Fi {}", cond.ssid.join("/")));
+        }
+    }
+    Ok(())
+}
+
+/// Check a pattern's `source` matchers against the application that opened the URL.
+/// An empty matcher list accepts any source; a non-empty list never matches an unknown source.
+pub(crate) fn source_matches(matchers: &[String], source: Option<&SourceApp>) -> bool {
+    if matchers.is_empty() {
+        return true;
+    }
+    let Some(source) = source else {
+        return false;
+    };
+    matchers
+        .iter()
+        .any(|m| source.identifiers().any(|id| name_matches(m.trim(), id)))
+}
+
+/// Case-insensitive comparison with optional `*` wildcards.
+fn name_matches(matcher: &str, name: &str) -> bool {
+    let matcher = matcher.to_lowercase();
+    let name = name.to_lowercase();
+    if matcher.contains('*') {
+        wildflower::Pattern::new(matcher.replace('?', r"\?").as_str()).matches(&name)
+    } else {
+        matcher == name
+    }
+}
+
+/// Check a `when` condition against a local wall-clock time. Invalid entries never match
+/// (they are reported by `Config::validate`).
+pub(crate) fn when_matches(when: &When, now: NaiveDateTime) -> bool {
+    // The early hours of a window that crosses midnight belong to the day it started on
+    let mut day = now.weekday();
+    if let Some(hours) = &when.hours {
+        let Ok((start, end)) = parse_hours(hours) else {
+            return false;
+        };
+        let minute = now.hour() * 60 + now.minute();
+        let inside = if start <= end {
+            minute >= start && minute < end
+        } else {
+            minute >= start || minute < end
+        };
+        if !inside {
+            return false;
+        }
+        if start > end && minute < end {
+            day = day.pred();
+        }
+    }
+    if !when.days.is_empty() {
+        let mut today = false;
+        for spec in &when.days {
+            match parse_days(spec) {
+                Ok(days) => today |= days.contains(&day),
+                Err(_) => return false,
+            }
+        }
+        if !today {
+            return false;
+        }
+    }
+    true
+}
+
+/// Parse a weekday (`mon`) or an inclusive range (`mon-fri`, `fri-mon`).
+pub(crate) fn parse_days(spec: &str) -> Result<Vec<Weekday>, String> {
+    let parse_one = |s: &str| -> Result<Weekday, String> {
+        s.trim()
+            .parse::<Weekday>()
+            .map_err(|_| format!("Invalid weekday '{}' (expected mon..sun)", s.trim()))
+    };
+    match spec.split_once('-') {
+        Some((from, to)) => {
+            let (from, to) = (parse_one(from)?, parse_one(to)?);
+            let mut days = vec![from];
+            let mut d = from;
+            while d != to {
+                d = d.succ();
+                days.push(d);
+            }
+            Ok(days)
+        }
+        None => Ok(vec![parse_one(spec)?]),
+    }
+}
+
+/// Parse `HH:MM-HH:MM` into minutes since midnight. `24:00` is accepted as an end time.
+pub(crate) fn parse_hours(spec: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid hours '{spec}' (expected HH:MM-HH:MM)");
+    let parse_time = |s: &str| -> Option<u32> {
+        let (h, m) = s.trim().split_once(':')?;
+        let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+        if m >= 60 || h > 24 || (h == 24 && m != 0) {
+            return None;
+        }
+        Some(h * 60 + m)
+    };
+    let (start, end) = spec.split_once('-').ok_or_else(invalid)?;
+    let (start, end) = (
+        parse_time(start).ok_or_else(invalid)?,
+        parse_time(end).ok_or_else(invalid)?,
+    );
+    if start == end || start == 24 * 60 {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+/// Short description of a `when` condition for `muxie route` output.
+pub(crate) fn describe_when(when: &When) -> String {
+    let mut parts = Vec::new();
+    if !when.days.is_empty() {
+        parts.push(when.days.join(","));
+    }
+    if let Some(hours) = &when.hours {
+        parts.push(hours.clone());
+    }
+    if parts.is_empty() {
+        "always".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn slack() -> SourceApp {
+        SourceApp {
+            pid: 42,
+            exe: Some("slack".into()),
+            app_id: Some("com.slack.Slack".into()),
+        }
+    }
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d)
+            .unwrap()
+            .and_hms_opt(h, mi, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn empty_matchers_accept_any_source() {
+        assert!(source_matches(&[], None));
+        assert!(source_matches(&[], Some(&slack())));
+    }
+
+    #[test]
+    fn matches_exe_or_app_id_case_insensitively() {
+        assert!(source_matches(&["Slack".into()], Some(&slack())));
+        assert!(source_matches(&["com.slack.slack".into()], Some(&slack())));
+        assert!(source_matches(&["com.slack.*".into()], Some(&slack())));
+        assert!(!source_matches(&["sla".into()], Some(&slack())));
+        assert!(!source_matches(&["thunderbird".into()], Some(&slack())));
+    }
+
+    #[test]
+    fn unknown_source_does_not_match_restricted_pattern() {
+        assert!(!source_matches(&["slack".into()], None));
+    }
+
+    struct FakeNetwork {
+        up: Vec<String>,
+        default: Vec<String>,
+        ssids: Vec<String>,
+    }
+
+    impl NetworkProbe for FakeNetwork {
+        fn interfaces_up(&self) -> Vec<String> {
+            self.up.clone()
+        }
+        fn default_route_interfaces(&self) -> Vec<String> {
+            self.default.clone()
+        }
+        fn wifi_ssids(&self) -> Vec<String> {
+            self.ssids.clone()
+        }
+    }
+
+    #[test]
+    fn network_conditions() {
+        let net = FakeNetwork {
+            up: vec!["lo".into(), "wlan0".into(), "wg0".into()],
+            default: vec!["wlan0".into()],
+            ssids: vec!["CorpNet".into()],
+        };
+        let cond = |interface: &[&str], default_route: &[&str], ssid: &[&str]| NetworkCondition {
+            interface: interface.iter().map(|s| s.to_string()).collect(),
+            default_route: default_route.iter().map(|s| s.to_string()).collect(),
+            ssid: ssid.iter().map(|s| s.to_string()).collect(),
+        };
+        assert!(network_matches(&cond(&["tun0", "wg*"], &[], &[]), &net).is_ok());
+        assert!(network_matches(&cond(&[], &["wlan0"], &["CorpNet"]), &net).is_ok());
+        assert_eq!(
+            network_matches(&cond(&["tun0"], &[], &[]), &net),
+            Err("interface tun0 is not up".to_string())
+        );
+        assert_eq!(
+            network_matches(&cond(&[], &["wg0"], &[]), &net),
+            Err("default route is not via wg0".to_string())
+        );
+        assert!(network_matches(&cond(&[], &[], &["corpnet"]), &net).is_err());
+    }
+
+    struct FakeDesktop {
+        env: Vec<(&'static str, &'static str)>,
+        workspace: Option<&'static str>,
+    }
+
+    impl DesktopProbe for FakeDesktop {
+        fn env_var(&self, name: &str) -> Option<String> {
+            self.env
+                .iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        }
+        fn active_workspace(&self) -> Option<String> {
+            self.workspace.map(String::from)
+        }
+    }
+
+    #[test]
+    fn env_and_workspace_conditions() {
+        let desktop = FakeDesktop {
+            env: vec![("XDG_CURRENT_DESKTOP", "ubuntu:GNOME"), ("FOO", "bar")],
+            workspace: Some("client-A"),
+        };
+        let mut pat = PatternEntry {
+            pattern: "*".into(),
+            browsers: vec!["B".into()],
+            env: [
+                ("XDG_CURRENT_DESKTOP".to_string(), "*GNOME*".to_string()),
+                ("FOO".to_string(), "bar".to_string()),
+            ]
+            .into(),
+            workspace: vec!["client-*".into()],
+            ..Default::default()
+        };
+        assert!(desktop_matches(&pat, &desktop).is_ok());
+
+        pat.workspace = vec!["client-B".into()];
+        assert_eq!(
+            desktop_matches(&pat, &desktop),
+            Err("workspace is 'client-A'".to_string())
+        );
+
+        pat.workspace.clear();
+        pat.env.insert("MISSING".into(), "x".into());
+        assert_eq!(
+            desktop_matches(&pat, &desktop),
+            Err("MISSING is not set".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_days_ranges() {
+        assert_eq!(
+            parse_days("mon-fri").unwrap(),
+            vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri
+            ]
+        );
+        assert_eq!(
+            parse_days("sat-mon").unwrap(),
+            vec![Weekday::Sat, Weekday::Sun, Weekday::Mon]
+        );
+        assert_eq!(parse_days("Sun").unwrap(), vec![Weekday::Sun]);
+        assert!(parse_days("funday").is_err());
+    }
+
+    #[test]
+    fn parse_hours_validation() {
+        assert_eq!(parse_hours("09:00-18:00").unwrap(), (540, 1080));
+        assert_eq!(parse_hours("22:00-24:00").unwrap(), (1320, 1440));
+        assert!(parse_hours("9am-5pm").is_err());
+        assert!(parse_hours("10:00-10:00").is_err());
+        assert!(parse_hours("10:60-11:00").is_err());
+    }
+
+    #[test]
+    fn work_hours_window() {
+        let when = When {
+            days: vec!["mon-fri".into()],
+            hours: Some("09:00-18:00".into()),
+        };
+        // 2025-09-01 is a Monday
+        assert!(when_matches(&when, at(2025, 9, 1, 9, 0)));
+        assert!(when_matches(&when, at(2025, 9, 5, 17, 59)));
+        assert!(!when_matches(&when, at(2025, 9, 5, 18, 0)));
+        assert!(!when_matches(&when, at(2025, 9, 1, 8, 59)));
+        assert!(!when_matches(&when, at(2025, 9, 6, 12, 0)));
+    }
+
+    #[test]
+    fn overnight_window_wraps() {
+        let when = When {
+            days: vec![],
+            hours: Some("22:00-06:00".into()),
+        };
+        assert!(when_matches(&when, at(2025, 9, 1, 23, 30)));
+        assert!(when_matches(&when, at(2025, 9, 1, 5, 59)));
+        assert!(!when_matches(&when, at(2025, 9, 1, 12, 0)));
+
+        // Friday night continues into Saturday morning, but not from Thursday night
+        let friday_night = When {
+            days: vec!["fri".into()],
+            hours: Some("22:00-06:00".into()),
+        };
+        assert!(when_matches(&friday_night, at(2025, 9, 5, 23, 0)));
+        assert!(when_matches(&friday_night, at(2025, 9, 6, 1, 0)));
+        assert!(!when_matches(&friday_night, at(2025, 9, 5, 1, 0)));
+        assert!(!when_matches(&friday_night, at(2025, 9, 6, 23, 0)));
+    }
+}
@@ -1,19 +1,28 @@
 use crate::browser::Browser;
 use crate::paths::config_path;
+use crate::sources::Provenance;
 use anyhow::{Context, Result, bail};
 use freedesktop_desktop_entry::{Iter, default_paths};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::io::{self, Write};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Config {
+    /// Format version of the file; see `muxie config migrate`.
     #[serde(default = "default_version")]
     pub version: u32,
 
+    /// Further files with browsers and patterns, merged after this file's own.
+    /// Relative paths are relative to this file; `*` is allowed in the file name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
     #[serde(default)]
     pub browsers: Vec<Browser>,
 
+    /// Routing rules, tried in order; the first match wins.
     #[serde(default)]
     pub patterns: Vec<PatternEntry>,
 
@@ -22,13 +31,31 @@ pub struct Config {
 
     #[serde(default)]
     pub dialog: DialogOptions,
+
+    #[serde(default)]
+    pub daemon: DaemonOptions,
+
+    #[serde(default)]
+    pub client: ClientOptions,
+
+    #[serde(default)]
+    pub logging: LoggingOptions,
+
+    #[serde(default)]
+    pub history: HistoryOptions,
+
+    /// Which file each browser and pattern came from.
+    #[serde(skip)]
+    pub provenance: Provenance,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Notifications {
+    /// Show desktop notifications, e.g. when a URL cannot be opened.
     #[serde(default = "default_true")]
     pub enabled: bool,
 
+    /// Show only the host of URLs in notifications and logs.
     #[serde(default = "default_true")]
     pub redact_urls: bool,
 }
@@ -37,8 +64,26 @@ fn default_true() -> bool {
     true
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: default_version(),
+            include: Vec::new(),
+            browsers: Vec::new(),
+            patterns: Vec::new(),
+            notifications: Notifications::default(),
+            dialog: DialogOptions::default(),
+            daemon: DaemonOptions::default(),
+            client: ClientOptions::default(),
+            logging: LoggingOptions::default(),
+            history: HistoryOptions::default(),
+            provenance: Provenance::default(),
+        }
+    }
+}
+
 fn default_version() -> u32 {
-    1
+    crate::migrate::CURRENT_VERSION
 }
 
 impl Default for Notifications {
@@ -50,8 +95,9 @@ impl Default for Notifications {
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct DialogOptions {
+    /// Program used for the browser selection dialog.
     #[serde(default = "default_provider")]
     pub provider: DialogProvider,
 }
@@ -64,13 +110,127 @@ impl Default for DialogOptions {
     }
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct DaemonOptions {
+    /// Exit after this many seconds without D-Bus calls; `0` keeps the daemon running.
+    /// D-Bus activation starts it again on the next request.
+    #[serde(default)]
+    pub idle_exit_secs: u64,
+    /// Also listen on `$XDG_RUNTIME_DIR/muxie.sock`, for clients without a session bus.
+    #[serde(default)]
+    pub socket: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ClientOptions {
+    /// How long `muxie open` waits for the daemon, including time spent in the selection
+    /// dialog; `0` waits indefinitely.
+    #[serde(default = "default_client_timeout")]
+    pub timeout_secs: u64,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            timeout_secs: default_client_timeout(),
+        }
+    }
+}
+
+fn default_client_timeout() -> u64 {
+    120
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct LoggingOptions {
+    /// Level for all targets; `-v` raises it to `debug`, `-vv` to `trace`.
+    #[serde(default = "default_log_level")]
+    pub level: LogLevel,
+    /// Per-target levels, e.g. `{ routing = "debug" }`. See `logging::TARGETS`.
+    #[serde(default)]
+    pub targets: BTreeMap<String, LogLevel>,
+    /// Where log records go.
+    #[serde(default)]
+    pub output: LogOutput,
+    /// Also append to `muxie.log` in the state directory.
+    #[serde(default)]
+    pub file: bool,
+    /// Size at which the log file is rotated to `muxie.log.1`.
+    #[serde(default = "default_log_file_max_kb")]
+    pub file_max_kb: u64,
+}
+
+impl Default for LoggingOptions {
+    fn default() -> Self {
+        LoggingOptions {
+            level: default_log_level(),
+            targets: BTreeMap::new(),
+            output: LogOutput::default(),
+            file: false,
+            file_max_kb: default_log_file_max_kb(),
+        }
+    }
+}
+
+fn default_log_level() -> LogLevel {
+    LogLevel::Warn
+}
+
+fn default_log_file_max_kb() -> u64 {
+    1024
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct HistoryOptions {
+    /// Record every opened URL in `history.jsonl` in the state directory.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Record only the host of each URL.
+    #[serde(default = "default_true")]
+    pub redact_urls: bool,
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        HistoryOptions {
+            enabled: false,
+            redact_urls: true,
+        }
+    }
+}
+
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Copy, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Where log records go besides the optional file. `auto` uses the journal when stderr
+/// is connected to it (e.g. under the systemd unit), stderr otherwise.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Default, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    #[default]
+    Auto,
+    Stderr,
+    Journald,
+}
+
 fn default_provider() -> DialogProvider {
     DialogProvider::Auto
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum DialogProvider {
+    /// The first of kdialog, zenity and yad that is installed.
     Auto,
     Kdialog,
     Zenity,
@@ -79,21 +239,25 @@ pub enum DialogProvider {
 
 pub fn read_config() -> Result<Config> {
     let config_path = config_path();
-    if !config_path.exists() {
+    let system = crate::paths::system_config_paths();
+    if !config_path.exists() && crate::paths::instance().is_some() {
+        bail!("Configuration file not found: {}", config_path.display());
+    }
+    let legacy = crate::paths::legacy_config_path();
+    if !config_path.exists() && legacy.is_file() {
+        log::warn!(
+            "Using the configuration file of muxie before 0.2.0 at {}; run `muxie config migrate --write` to convert it",
+            legacy.display()
+        );
+        return crate::migrate::load_legacy(&legacy);
+    }
+    if !config_path.exists() && !system.iter().any(|f| f.is_file()) {
         bail!(
             "Configuration not found. Please run 'muxie install' first to set up browser configuration at: {}",
             config_path.display()
         );
     }
-    let config_text = std::fs::read_to_string(&config_path)
-        .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
-    let config: Config = toml::from_str(&config_text).with_context(|| {
-        format!(
-            "Failed to parse config file (TOML): {}",
-            config_path.display()
-        )
-    })?;
-    Ok(config)
+    crate::sources::load_layered(&config_path, &system)
 }
 
 pub fn installed_browsers() -> Vec<Browser> {
@@ -125,12 +289,17 @@ pub fn installed_browsers() -> Vec<Browser> {
 pub fn ensure_config() -> Result<()> {
     let config_path = config_path();
     if !config_path.exists() {
+        // Browsers defined in system-wide files are left to them
+        let provided: Vec<String> =
+            crate::sources::load_layered(&config_path, &crate::paths::system_config_paths())
+                .map(|c| c.browsers.into_iter().map(|b| b.name).collect())
+                .unwrap_or_default();
         let config = Config {
-            version: default_version(),
-            browsers: installed_browsers(),
-            patterns: Vec::new(),
-            notifications: Notifications::default(),
-            dialog: DialogOptions::default(),
+            browsers: installed_browsers()
+                .into_iter()
+                .filter(|b| !provided.contains(&b.name))
+                .collect(),
+            ..Default::default()
         };
         let config_text = toml::to_string_pretty(&config)
             .context("Failed to serialize default config to TOML")?;
@@ -146,34 +315,101 @@ pub fn ensure_config() -> Result<()> {
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The configuration is rejected.
+    Error,
+    /// The configuration works, but part of it has no effect.
+    Warning,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct ValidationError {
+    pub severity: Severity,
     pub code: String,
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
+    /// Included or system-wide file the issue is in; `None` for the main configuration file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
 }
 
 impl ValidationError {
     fn new(code: &str, message: impl Into<String>, path: impl Into<Option<String>>) -> Self {
         ValidationError {
+            severity: Severity::Error,
             code: code.to_string(),
             message: message.into(),
             path: path.into(),
+            file: None,
+        }
+    }
+
+    fn warning(code: &str, message: impl Into<String>, path: impl Into<Option<String>>) -> Self {
+        ValidationError {
+            severity: Severity::Warning,
+            ..ValidationError::new(code, message, path)
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ValidationResult {
-    pub errors: Vec<ValidationError>,
+    /// Errors and warnings, in the order they were found.
+    pub issues: Vec<ValidationError>,
 }
+/// `pattern.browser.unknown` errors for the browsers and fallbacks of pattern `pi` that
+/// are not in `names`.
+fn unknown_pattern_browsers(
+    pi: usize,
+    pat: &PatternEntry,
+    names: &HashSet<&str>,
+) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    for (fi, name) in pat.fallback.iter().enumerate() {
+        if !names.contains(name.as_str()) {
+            errors.push(ValidationError::new(
+                "pattern.browser.unknown",
+                format!("Unknown browser in pattern fallback: '{name}'"),
+                Some(format!("patterns[{pi}].fallback[{fi}]")),
+            ));
+        }
+    }
+    for (bi, name) in pat.browsers.iter().enumerate() {
+        if !names.contains(name.as_str()) {
+            errors.push(ValidationError::new(
+                "pattern.browser.unknown",
+                format!("Unknown browser in pattern: '{name}'"),
+                Some(format!("patterns[{pi}].browsers[{bi}]")),
+            ));
+        }
+    }
+    errors
+}
+
 impl Config {
     /// Validate semantic constraints. Does not perform I/O checks unless `strict` is true.
     pub fn validate(&self, strict: bool) -> ValidationResult {
         self.validate_with_path(strict, None)
     }
 
+    /// Patterns naming browsers that are not configured. Reported by strict validation
+    /// only, but the daemon also refuses to reload a file with them.
+    pub(crate) fn unknown_browsers(&self) -> Vec<ValidationError> {
+        let names: HashSet<&str> = self.browsers.iter().map(|b| b.name.as_str()).collect();
+        self.patterns
+            .iter()
+            .enumerate()
+            .flat_map(|(pi, pat)| unknown_pattern_browsers(pi, pat, &names))
+            .map(|mut issue| {
+                self.provenance.attribute(&mut issue);
+                issue
+            })
+            .collect()
+    }
+
     /// Like `validate`, but resolves executables against a provided PATH (for testing).
     pub fn validate_with_path(
         &self,
@@ -248,15 +484,139 @@ impl Config {
                     Some(format!("patterns[{pi}].pattern")),
                 ));
             }
-            for (bi, name) in pat.browsers.iter().enumerate() {
-                if strict && !name_set.contains(name.as_str()) {
+            for (si, src) in pat.source.iter().enumerate() {
+                if src.trim().is_empty() {
+                    errors.push(ValidationError::new(
+                        "pattern.source.empty",
+                        "Source matcher must not be empty",
+                        Some(format!("patterns[{pi}].source[{si}]")),
+                    ));
+                }
+            }
+            if let Some(when) = &pat.when {
+                for (di, day) in when.days.iter().enumerate() {
+                    if let Err(msg) = crate::condition::parse_days(day) {
+                        errors.push(ValidationError::new(
+                            "pattern.when.days.invalid",
+                            msg,
+                            Some(format!("patterns[{pi}].when.days[{di}]")),
+                        ));
+                    }
+                }
+                if let Some(hours) = &when.hours
+                    && let Err(msg) = crate::condition::parse_hours(hours)
+                {
                     errors.push(ValidationError::new(
-                        "pattern.browser.unknown",
-                        format!("Unknown browser in pattern: '{name}'"),
-                        Some(format!("patterns[{pi}].browsers[{bi}]")),
+                        "pattern.when.hours.invalid",
+                        msg,
+                        Some(format!("patterns[{pi}].when.hours")),
                     ));
                 }
             }
+            if let Some(net) = &pat.network
+                && net.interface.is_empty()
+                && net.default_route.is_empty()
+                && net.ssid.is_empty()
+            {
+                errors.push(ValidationError::new(
+                    "pattern.network.empty",
+                    "Network condition must set at least one of interface, default_route or ssid",
+                    Some(format!("patterns[{pi}].network")),
+                ));
+            }
+            for key in pat.env.keys() {
+                if key.trim().is_empty() || key.contains('=') {
+                    errors.push(ValidationError::new(
+                        "pattern.env.invalid_name",
+                        format!("Invalid environment variable name: '{key}'"),
+                        Some(format!("patterns[{pi}].env")),
+                    ));
+                }
+            }
+            for (wi, ws) in pat.workspace.iter().enumerate() {
+                if ws.trim().is_empty() {
+                    errors.push(ValidationError::new(
+                        "pattern.workspace.empty",
+                        "Workspace name must not be empty",
+                        Some(format!("patterns[{pi}].workspace[{wi}]")),
+                    ));
+                }
+            }
+            if !pat.fallback.is_empty() && !pat.has_conditions() {
+                errors.push(ValidationError::new(
+                    "pattern.fallback.unused",
+                    "Fallback browsers are only used with a 'when', 'network', 'env' or 'workspace' condition",
+                    Some(format!("patterns[{pi}].fallback")),
+                ));
+            }
+            if strict {
+                errors.extend(unknown_pattern_browsers(pi, pat, &name_set));
+            }
+
+            // Patterns that can never be selected
+            if pat.browsers.is_empty() {
+                errors.push(ValidationError::warning(
+                    "pattern.browsers.empty",
+                    format!("Pattern '{}' lists no browsers and is ignored", pat.pattern),
+                    Some(format!("patterns[{pi}].browsers")),
+                ));
+                continue;
+            }
+            if !pat.browsers.iter().any(|b| name_set.contains(b.as_str())) {
+                errors.push(ValidationError::warning(
+                    "pattern.browsers.unresolved",
+                    format!(
+                        "None of the browsers of pattern '{}' are configured; it is skipped",
+                        pat.pattern
+                    ),
+                    Some(format!("patterns[{pi}].browsers")),
+                ));
+                continue;
+            }
+            // Only an earlier pattern without conditions that can open a URL always wins
+            let shadowing = self.patterns[..pi].iter().enumerate().find(|(_, earlier)| {
+                earlier.source.is_empty()
+                    && !earlier.has_conditions()
+                    && earlier
+                        .browsers
+                        .iter()
+                        .any(|b| name_set.contains(b.as_str()))
+                    && crate::pattern::subsumes(&earlier.pattern, &pat.pattern)
+            });
+            if let Some((ei, earlier)) = shadowing {
+                let (code, message) = if earlier.pattern == pat.pattern {
+                    (
+                        "pattern.duplicate",
+                        format!("Pattern '{}' duplicates patterns[{ei}]", pat.pattern),
+                    )
+                } else {
+                    (
+                        "pattern.shadowed",
+                        format!(
+                            "Pattern '{}' is shadowed by patterns[{ei}] '{}', which matches every URL it matches",
+                            pat.pattern, earlier.pattern
+                        ),
+                    )
+                };
+                errors.push(ValidationError::warning(
+                    code,
+                    message,
+                    Some(format!("patterns[{pi}].pattern")),
+                ));
+            }
+        }
+
+        for target in self.logging.targets.keys() {
+            if !crate::logging::TARGETS.contains(&target.as_str()) {
+                errors.push(ValidationError::new(
+                    "logging.target.unknown",
+                    format!(
+                        "Unknown log target '{target}' (expected one of: {})",
+                        crate::logging::TARGETS.join(", ")
+                    ),
+                    Some(format!("logging.targets.{target}")),
+                ));
+            }
         }
 
         // Strict: ensure executables are resolvable from PATH
@@ -291,13 +651,44 @@ impl Config {
             }
         }
 
-        ValidationResult { errors }
+        for issue in &mut errors {
+            self.provenance.attribute(issue);
+        }
+        for o in &self.provenance.overridden {
+            errors.push(ValidationError {
+                file: o.origin.file.as_ref().map(|f| f.display().to_string()),
+                ..ValidationError::warning(
+                    "browser.locked",
+                    format!(
+                        "Browser '{}' is locked by {} and cannot be redefined; this definition is ignored",
+                        o.name,
+                        o.locked_by.display()
+                    ),
+                    Some(format!("browsers[{}]", o.origin.index)),
+                )
+            });
+        }
+        ValidationResult { issues: errors }
     }
 }
 
 impl ValidationResult {
     pub fn is_empty(&self) -> bool {
-        self.errors.is_empty()
+        self.issues.is_empty()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationError> {
+        self.issues.iter().filter(|e| e.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationError> {
+        self.issues
+            .iter()
+            .filter(|e| e.severity == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
     }
 
     /// Print formatted validation errors to stderr, including a header line.
@@ -307,13 +698,22 @@ impl ValidationResult {
 
     /// Print formatted validation errors to any writer (for tests).
     pub fn print_to<W: Write>(&self, mut w: W) {
-        let count = self.errors.len();
+        let count = self.issues.len();
         let _ = writeln!(w, "Found {count} validation issue(s):");
-        for d in &self.errors {
+        for d in &self.issues {
+            let prefix = match d.severity {
+                Severity::Error => "",
+                Severity::Warning => "warning: ",
+            };
             if let Some(path) = &d.path {
-                let _ = writeln!(w, "- {}: {} — {}", d.code, path, d.message);
+                let file = d
+                    .file
+                    .as_deref()
+                    .map(|f| format!("{f}: "))
+                    .unwrap_or_default();
+                let _ = writeln!(w, "- {prefix}{}: {file}{path} — {}", d.code, d.message);
             } else {
-                let _ = writeln!(w, "- {}: {}", d.code, d.message);
+                let _ = writeln!(w, "- {prefix}{}: {}", d.code, d.message);
             }
         }
     }
@@ -321,21 +721,205 @@ impl ValidationResult {
 
 // validate_config removed; call Config::validate(strict) directly
 
+/// Browsers and patterns added or removed between two configurations. Patterns are
+/// compared together with their browser lists, so a changed list shows up as a removal
+/// and an addition.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ConfigDiff {
+    pub browsers_added: Vec<String>,
+    pub browsers_removed: Vec<String>,
+    pub patterns_added: Vec<String>,
+    pub patterns_removed: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub(crate) fn between(old: &Config, new: &Config) -> Self {
+        fn changes(old: Vec<String>, new: Vec<String>) -> (Vec<String>, Vec<String>) {
+            let added = new.iter().filter(|n| !old.contains(n)).cloned().collect();
+            let removed = old.iter().filter(|o| !new.contains(o)).cloned().collect();
+            (added, removed)
+        }
+        let browsers = |cfg: &Config| cfg.browsers.iter().map(|b| b.name.clone()).collect();
+        let patterns = |cfg: &Config| {
+            cfg.patterns
+                .iter()
+                .map(|p| format!("'{}' → {}", p.pattern, p.browsers.join(", ")))
+                .collect()
+        };
+        let (browsers_added, browsers_removed) = changes(browsers(old), browsers(new));
+        let (patterns_added, patterns_removed) = changes(patterns(old), patterns(new));
+        ConfigDiff {
+            browsers_added,
+            browsers_removed,
+            patterns_added,
+            patterns_removed,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == ConfigDiff::default()
+    }
+}
+
+impl std::fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no browsers or patterns added or removed");
+        }
+        let mut parts: Vec<String> = Vec::new();
+        for (label, items) in [
+            ("browsers added", &self.browsers_added),
+            ("browsers removed", &self.browsers_removed),
+            ("patterns added", &self.patterns_added),
+            ("patterns removed", &self.patterns_removed),
+        ] {
+            if !items.is_empty() {
+                parts.push(format!("{label}: {}", items.join("; ")));
+            }
+        }
+        write!(f, "{}", parts.join(" | "))
+    }
+}
+
+/// JSON Schema (draft 7) of the configuration file, for editors such as Taplo.
+pub(crate) fn json_schema() -> serde_json::Value {
+    let schema = schemars::generate::SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<Config>();
+    let mut schema = serde_json::to_value(schema).expect("schemas serialize");
+    schema["title"] = "muxie.toml".into();
+    schema
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Assert that every key of `value` is described at the matching place in `schema`.
+    fn assert_described(
+        root: &serde_json::Value,
+        schema: &serde_json::Value,
+        value: &serde_json::Value,
+        path: &str,
+    ) {
+        use serde_json::Value;
+        let mut schema = schema;
+        // Fields with a default refer to their type through `allOf: [T]`
+        while let Some(next) = schema.get("$ref").or_else(|| schema.pointer("/allOf/0")) {
+            schema = match next.as_str() {
+                Some(r) => &root["definitions"][r.trim_start_matches("#/definitions/")],
+                None => next,
+            };
+        }
+        // `Option<T>` is `anyOf: [T, null]`
+        if let Some(Value::Array(variants)) = schema.get("anyOf") {
+            let variant = variants
+                .iter()
+                .find(|v| v.get("type") != Some(&"null".into()))
+                .unwrap();
+            return assert_described(root, variant, value, path);
+        }
+        match value {
+            Value::Object(map) => {
+                for (key, item) in map {
+                    let sub = schema["properties"]
+                        .get(key)
+                        .or_else(|| schema.get("additionalProperties").filter(|a| a.is_object()))
+                        .unwrap_or_else(|| panic!("{path}.{key} is missing from the schema"));
+                    assert_described(root, sub, item, &format!("{path}.{key}"));
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    assert_described(root, &schema["items"], item, &format!("{path}[]"));
+                }
+            }
+            Value::String(s) => {
+                let allowed: Vec<&Value> = match (schema.get("enum"), schema.get("oneOf")) {
+                    (Some(Value::Array(values)), _) => values.iter().collect(),
+                    (_, Some(Value::Array(variants))) => variants
+                        .iter()
+                        .flat_map(|v| {
+                            v.get("const")
+                                .into_iter()
+                                .chain(v["enum"].as_array().into_iter().flatten())
+                        })
+                        .collect(),
+                    _ => return,
+                };
+                assert!(
+                    allowed.contains(&&Value::from(s.as_str())),
+                    "{path} = {s:?} is not allowed by the schema"
+                );
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn schema_describes_all_fields() {
+        let toml = r#"
+include = ["work.toml"]
+
+[[browsers]]
+name = "Firefox"
+executable = "firefox"
+args = ["%u"]
+locked = true
+
+[[patterns]]
+pattern = "example.com"
+browsers = ["Firefox"]
+prefer_running = true
+source = ["slack"]
+when = { days = ["mon-fri"], hours = "09:00-17:00" }
+network = { interface = ["tun0"], default_route = ["wg0"], ssid = ["Office"] }
+env = { XDG_CURRENT_DESKTOP = "*GNOME*" }
+workspace = ["work"]
+fallback = ["Firefox"]
+locked = true
+
+[dialog]
+provider = "zenity"
+
+[daemon]
+idle_exit_secs = 60
+socket = true
+
+[logging]
+level = "debug"
+targets = { routing = "trace" }
+output = "journald"
+file = true
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        let schema = json_schema();
+        assert_described(
+            &schema,
+            &schema,
+            &serde_json::to_value(&config).unwrap(),
+            "config",
+        );
+
+        // The copy in the repository is what editors load
+        let committed: serde_json::Value =
+            serde_json::from_str(include_str!("../resources/muxie.schema.json")).unwrap();
+        assert!(
+            committed == schema,
+            "resources/muxie.schema.json is out of date; regenerate it with `cargo run -- config schema > resources/muxie.schema.json`"
+        );
+    }
+
     #[test]
     fn validate_detects_empty() {
         let cfg = Config {
             version: 1,
             browsers: vec![],
             patterns: vec![],
-            notifications: Notifications::default(),
-            dialog: DialogOptions::default(),
+            ..Default::default()
         };
         let res = cfg.validate(false);
-        assert!(res.errors.iter().any(|e| e.code == "browsers.empty"));
+        assert!(res.issues.iter().any(|e| e.code == "browsers.empty"));
     }
 
     #[test]
@@ -347,25 +931,26 @@ mod tests {
                     name: "A".into(),
                     executable: "".into(),
                     args: vec![],
+                    locked: false,
                 },
                 Browser {
                     name: "A".into(),
                     executable: "firefox".into(),
                     args: vec![],
+                    locked: false,
                 },
             ],
             patterns: vec![],
-            notifications: Notifications::default(),
-            dialog: DialogOptions::default(),
+            ..Default::default()
         };
         let res = cfg.validate(false);
         assert!(
-            res.errors
+            res.issues
                 .iter()
                 .any(|e| e.code == "browser.name.duplicate")
         );
         assert!(
-            res.errors
+            res.issues
                 .iter()
                 .any(|e| e.code == "browser.executable.empty")
         );
@@ -379,13 +964,13 @@ mod tests {
                 name: "   ".into(),
                 executable: "firefox".into(),
                 args: vec![],
+                locked: false,
             }],
             patterns: vec![],
-            notifications: Notifications::default(),
-            dialog: DialogOptions::default(),
+            ..Default::default()
         };
         let res = cfg.validate(false);
-        assert!(res.errors.iter().any(|e| e.code == "browser.name.empty"));
+        assert!(res.issues.iter().any(|e| e.code == "browser.name.empty"));
     }
 
     #[test]
@@ -396,38 +981,230 @@ mod tests {
                 name: "B".into(),
                 executable: "firefox".into(),
                 args: vec![],
+                locked: false,
             }],
             patterns: vec![
                 PatternEntry {
                     pattern: "".into(),
                     browsers: vec!["B".into()],
+                    ..Default::default()
                 },
                 PatternEntry {
                     pattern: "foo\nbar".into(),
                     browsers: vec!["B".into()],
+                    ..Default::default()
                 },
             ],
-            notifications: Notifications::default(),
-            dialog: DialogOptions::default(),
+            ..Default::default()
         };
         let res = cfg.validate(false);
-        assert!(res.errors.iter().any(|e| e.code == "pattern.empty"));
-        assert!(res.errors.iter().any(|e| e.code == "pattern.newline"));
+        assert!(res.issues.iter().any(|e| e.code == "pattern.empty"));
+        assert!(res.issues.iter().any(|e| e.code == "pattern.newline"));
+    }
+
+    #[test]
+    fn validate_pattern_when() {
+        let cfg = Config {
+            version: 1,
+            browsers: vec![Browser {
+                name: "B".into(),
+                executable: "firefox".into(),
+                args: vec![],
+                locked: false,
+            }],
+            patterns: vec![PatternEntry {
+                pattern: "*".into(),
+                browsers: vec!["B".into()],
+                when: Some(When {
+                    days: vec!["mon-fri".into(), "funday".into()],
+                    hours: Some("9am-5pm".into()),
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let res = cfg.validate(false);
+        let codes: Vec<(&str, Option<&str>)> = res
+            .issues
+            .iter()
+            .map(|e| (e.code.as_str(), e.path.as_deref()))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                (
+                    "pattern.when.days.invalid",
+                    Some("patterns[0].when.days[1]")
+                ),
+                ("pattern.when.hours.invalid", Some("patterns[0].when.hours")),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_pattern_network_and_fallback() {
+        let cfg = Config {
+            version: 1,
+            browsers: vec![Browser {
+                name: "B".into(),
+                executable: "firefox".into(),
+                args: vec![],
+                locked: false,
+            }],
+            patterns: vec![
+                PatternEntry {
+                    pattern: "intranet".into(),
+                    browsers: vec!["B".into()],
+                    network: Some(NetworkCondition::default()),
+                    ..Default::default()
+                },
+                PatternEntry {
+                    pattern: "*".into(),
+                    browsers: vec!["B".into()],
+                    fallback: vec!["B".into()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let res = cfg.validate(false);
+        assert!(res.issues.iter().any(|e| e.code == "pattern.network.empty"
+            && e.path.as_deref() == Some("patterns[0].network")));
+        assert!(
+            res.issues
+                .iter()
+                .any(|e| e.code == "pattern.fallback.unused"
+                    && e.path.as_deref() == Some("patterns[1].fallback"))
+        );
+    }
+
+    #[test]
+    fn validate_pattern_env_and_workspace() {
+        let cfg = Config {
+            version: 1,
+            browsers: vec![Browser {
+                name: "B".into(),
+                executable: "firefox".into(),
+                args: vec![],
+                locked: false,
+            }],
+            patterns: vec![PatternEntry {
+                pattern: "*".into(),
+                browsers: vec!["B".into()],
+                env: BTreeMap::from([(" ".to_string(), "x".to_string())]),
+                workspace: vec!["".into()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let res = cfg.validate(false);
+        assert!(
+            res.issues
+                .iter()
+                .any(|e| e.code == "pattern.env.invalid_name")
+        );
+        assert!(
+            res.issues
+                .iter()
+                .any(|e| e.code == "pattern.workspace.empty"
+                    && e.path.as_deref() == Some("patterns[0].workspace[0]"))
+        );
+    }
+
+    #[test]
+    fn validate_pattern_source_empty() {
+        let cfg = Config {
+            version: 1,
+            browsers: vec![Browser {
+                name: "B".into(),
+                executable: "firefox".into(),
+                args: vec![],
+                locked: false,
+            }],
+            patterns: vec![PatternEntry {
+                pattern: "*".into(),
+                browsers: vec!["B".into()],
+                source: vec!["slack".into(), " ".into()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let res = cfg.validate(false);
+        let err = res
+            .issues
+            .iter()
+            .find(|e| e.code == "pattern.source.empty")
+            .unwrap();
+        assert_eq!(err.path.as_deref(), Some("patterns[0].source[1]"));
+    }
+
+    #[test]
+    fn validate_warns_about_unreachable_patterns() {
+        let pattern = |p: &str, browsers: &[&str]| PatternEntry {
+            pattern: p.into(),
+            browsers: browsers.iter().map(|b| b.to_string()).collect(),
+            ..Default::default()
+        };
+        let cfg = Config {
+            version: 1,
+            browsers: vec![Browser {
+                name: "B".into(),
+                executable: "firefox".into(),
+                args: vec![],
+                locked: false,
+            }],
+            patterns: vec![
+                pattern("ignored.org", &[]),
+                pattern("missing.org", &["Nope"]),
+                // Neither skipped pattern shadows anything
+                pattern("ignored.org/x", &["B"]),
+                PatternEntry {
+                    env: BTreeMap::from([("X".into(), "1".into())]),
+                    ..pattern("example.com", &["B"])
+                },
+                pattern("example.com", &["B"]),
+                pattern("example.com", &["B"]),
+                pattern("docs.example.com", &["B"]),
+                pattern("*", &["B"]),
+                pattern("later.org", &["B"]),
+            ],
+            ..Default::default()
+        };
+        let res = cfg.validate(false);
+        assert!(!res.has_errors());
+        assert_eq!(
+            res.warnings()
+                .map(|e| (e.code.as_str(), e.path.as_deref().unwrap()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("pattern.browsers.empty", "patterns[0].browsers"),
+                ("pattern.browsers.unresolved", "patterns[1].browsers"),
+                ("pattern.duplicate", "patterns[5].pattern"),
+                ("pattern.shadowed", "patterns[6].pattern"),
+                ("pattern.shadowed", "patterns[8].pattern"),
+            ]
+        );
+        // Strict mode adds the unknown browser as an error
+        assert!(cfg.validate(true).has_errors());
     }
 
     #[test]
     fn validation_result_print_format() {
         let res = ValidationResult {
-            errors: vec![
+            issues: vec![
                 ValidationError {
+                    severity: Severity::Error,
                     code: "code1".into(),
                     message: "msg1".into(),
                     path: Some("path1".into()),
+                    file: None,
                 },
                 ValidationError {
+                    severity: Severity::Warning,
                     code: "code2".into(),
                     message: "msg2".into(),
                     path: None,
+                    file: None,
                 },
             ],
         };
@@ -436,7 +1213,79 @@ mod tests {
         let s = String::from_utf8(buf).unwrap();
         assert!(s.starts_with("Found 2 validation issue(s):\n"));
         assert!(s.contains("- code1: path1 — msg1\n"));
-        assert!(s.contains("- code2: msg2\n"));
+        assert!(s.contains("- warning: code2: msg2\n"));
+    }
+
+    #[test]
+    fn daemon_idle_exit_defaults_to_disabled() {
+        let cfg: Config = toml::from_str("version = 1\n").unwrap();
+        assert_eq!(cfg.daemon.idle_exit_secs, 0);
+        assert_eq!(cfg.client.timeout_secs, 120);
+        assert!(!cfg.daemon.socket);
+        let cfg: Config =
+            toml::from_str("[daemon]\nidle_exit_secs = 600\nsocket = true\n").unwrap();
+        assert_eq!(cfg.daemon.idle_exit_secs, 600);
+        assert!(cfg.daemon.socket);
+    }
+
+    #[test]
+    fn diff_lists_added_and_removed_entries() {
+        let browser = |name: &str| Browser {
+            name: name.into(),
+            executable: name.to_lowercase(),
+            args: vec![],
+            locked: false,
+        };
+        let pattern = |pattern: &str, browser: &str| PatternEntry {
+            pattern: pattern.into(),
+            browsers: vec![browser.into()],
+            ..Default::default()
+        };
+        let old: Config = Config {
+            browsers: vec![browser("Firefox"), browser("Chromium")],
+            patterns: vec![
+                pattern("github.com", "Firefox"),
+                pattern("work", "Chromium"),
+            ],
+            ..toml::from_str("").unwrap()
+        };
+        let mut new = old.clone();
+        assert!(ConfigDiff::between(&old, &new).is_empty());
+
+        new.browsers.remove(1);
+        new.browsers.push(browser("Work"));
+        new.patterns[1].browsers = vec!["Work".into()];
+        let diff = ConfigDiff::between(&old, &new);
+        assert_eq!(diff.browsers_added, vec!["Work"]);
+        assert_eq!(diff.browsers_removed, vec!["Chromium"]);
+        assert_eq!(
+            diff.to_string(),
+            "browsers added: Work | browsers removed: Chromium | patterns added: 'work' → Work | patterns removed: 'work' → Chromium"
+        );
+    }
+
+    #[test]
+    fn logging_section_parses_and_validates_targets() {
+        let cfg: Config = toml::from_str(
+            "[logging]\nlevel = \"info\"\ntargets = { routing = \"trace\", nope = \"off\" }\noutput = \"journald\"\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.logging.level, LogLevel::Info);
+        assert_eq!(cfg.logging.targets["routing"], LogLevel::Trace);
+        assert_eq!(cfg.logging.output, LogOutput::Journald);
+        assert!(!cfg.logging.file);
+        let res = cfg.validate(false);
+        assert_eq!(
+            res.issues
+                .iter()
+                .filter(|e| e.code.starts_with("logging."))
+                .map(|e| (e.code.as_str(), e.path.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![("logging.target.unknown", Some("logging.targets.nope"))]
+        );
+        let default: Config = toml::from_str("").unwrap();
+        assert_eq!(default.logging, LoggingOptions::default());
+        assert_eq!(default.logging.level, LogLevel::Warn);
     }
 
     #[test]
@@ -448,24 +1297,107 @@ mod tests {
                 name: "B".into(),
                 executable: "sh".into(),
                 args: vec![],
+                locked: false,
             }],
             patterns: vec![],
-            notifications: Notifications::default(),
             dialog: DialogOptions {
                 provider: DialogProvider::Zenity,
             },
+            ..Default::default()
         };
         let res = cfg.validate_with_path(true, Some(OsStr::new("/__muxie_empty")));
         assert!(
-            res.errors
+            res.issues
                 .iter()
                 .any(|e| e.code == "dialog.provider.not_found")
         );
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct PatternEntry {
+    /// URL pattern: a substring of the URL, or a wildcard pattern with `*` that must match
+    /// the whole URL.
     pub pattern: String,
+    /// Browser names in preference order; with several, the selection dialog is shown.
     pub browsers: Vec<String>,
+
+    /// Try browsers that are already running first, skipping the selection
+    /// dialog when only one of them is running.
+    #[serde(default)]
+    pub prefer_running: bool,
+
+    /// Only match links opened by one of these applications. Each entry is compared
+    /// (case-insensitively, `*` wildcards allowed) against the source executable name
+    /// and its desktop/Flatpak application ID. Empty means any source.
+    #[serde(default)]
+    pub source: Vec<String>,
+
+    /// Only match during these local days/hours.
+    #[serde(default)]
+    pub when: Option<When>,
+
+    /// Only match when the network is in this state (e.g. a VPN is connected).
+    #[serde(default)]
+    pub network: Option<NetworkCondition>,
+
+    /// Only match when the caller's environment variables have these values
+    /// (`*` wildcards allowed), e.g. `{ XDG_CURRENT_DESKTOP = "*GNOME*" }`.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Only match when the focused Sway/i3/Hyprland workspace has one of these names.
+    #[serde(default)]
+    pub workspace: Vec<String>,
+
+    /// Browsers to use instead when the URL matches but a `when`, `network`, `env`
+    /// or `workspace` condition does not hold. Without a fallback, routing continues with later patterns.
+    #[serde(default)]
+    pub fallback: Vec<String>,
+
+    /// Set in a system-wide configuration file to route matching URLs before any user
+    /// pattern. Has no effect in the user's own files.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+}
+
+/// Network state condition on a pattern. Every non-empty field must be satisfied;
+/// entries within a field are alternatives and may use `*` wildcards.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct NetworkCondition {
+    /// One of these interfaces is up, e.g. `["tun0", "wg*"]`.
+    #[serde(default)]
+    pub interface: Vec<String>,
+
+    /// The default route goes through one of these interfaces.
+    #[serde(default)]
+    pub default_route: Vec<String>,
+
+    /// Connected to one of these Wi-Fi networks (via NetworkManager).
+    #[serde(default)]
+    pub ssid: Vec<String>,
+}
+
+impl PatternEntry {
+    /// Whether the entry has conditions beyond the URL and source match, i.e. whether
+    /// `fallback` can apply.
+    pub fn has_conditions(&self) -> bool {
+        self.when.is_some()
+            || self.network.is_some()
+            || !self.env.is_empty()
+            || !self.workspace.is_empty()
+    }
+}
+
+/// Time-of-day and weekday condition on a pattern, evaluated in local time.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct When {
+    /// Weekdays (`mon`..`sun`) or ranges such as `mon-fri`. Empty means every day.
+    #[serde(default)]
+    pub days: Vec<String>,
+
+    /// Time window `HH:MM-HH:MM` (start inclusive, end exclusive). A window whose end is
+    /// before its start spans midnight, e.g. `22:00-06:00`.
+    #[serde(default)]
+    pub hours: Option<String>,
 }
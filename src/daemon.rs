@@ -1,111 +1,655 @@
-use crate::config::{Config, read_config};
+use crate::config::{Config, ConfigDiff, ValidationError, read_config};
+use crate::logging::{DAEMON, DBUS, WATCHER};
 use crate::notify::redact_url;
+use crate::socket::{Request, Response, RouteInfo, SocketServer, Status};
+use crate::sources::WatchSet;
 use anyhow::{Context, Result};
+use async_signal::{Signal, Signals};
+use futures_lite::{StreamExt, future};
+use std::collections::BTreeSet;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use zbus::interface;
+use zbus::object_server::SignalEmitter;
 
 pub const DBUS_SERVICE: &str = "xyz.adf.Muxie";
 pub const DBUS_INTERFACE: &str = "xyz.adf.Muxie1"; // Note: must match the dbus_interface attribute
 pub const DBUS_PATH: &str = "/xyz/adf/Muxie";
+
+/// Bus name of this instance: `DBUS_SERVICE`, with the instance name appended when a
+/// configuration file other than the default one is used.
+pub(crate) fn bus_name() -> String {
+    match crate::paths::instance() {
+        Some(instance) => format!("{DBUS_SERVICE}.{instance}"),
+        None => DBUS_SERVICE.to_string(),
+    }
+}
 pub const DBUS_METHOD_RELOAD: &str = "ReloadConfig";
 pub const DBUS_METHOD_OPEN_URL_FD: &str = "OpenUrlFd";
+pub const DBUS_ERROR_CANCELED: &str = "xyz.adf.Muxie1.Error.Canceled";
+
+/// Errors returned by routing methods, as `xyz.adf.Muxie1.Error.*` D-Bus errors.
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "xyz.adf.Muxie1.Error")]
+pub(crate) enum MuxieError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    /// The user dismissed the selection dialog.
+    Canceled(String),
+    Failed(String),
+}
 
+/// Current configuration. Requests take a snapshot of the inner `Arc` and release the
+/// lock right away, so reloads never wait for an open selection dialog.
+type SharedConfig = Arc<Mutex<Arc<Config>>>;
+
+#[derive(Clone)]
 struct MuxieDaemon {
-    cfg: Arc<Mutex<Config>>,
+    cfg: SharedConfig,
+    /// Unix time of the last successful configuration load.
+    last_reload: Arc<AtomicU64>,
+    activity: Arc<Activity>,
     no_notify: bool,
-    verbose: u8,
 }
 
 impl MuxieDaemon {
-    fn new(cfg: Arc<Mutex<Config>>, no_notify: bool, verbose: u8) -> Self {
+    fn new(cfg: SharedConfig, no_notify: bool) -> Self {
         Self {
             cfg,
+            last_reload: Arc::new(AtomicU64::new(unix_now())),
+            activity: Arc::new(Activity::new()),
             no_notify,
-            verbose,
         }
     }
-}
 
-#[interface(name = "xyz.adf.Muxie1")]
-impl MuxieDaemon {
-    #[allow(non_snake_case)]
-    fn OpenUrlFd(&self, fd: zbus::zvariant::OwnedFd) -> zbus::fdo::Result<()> {
-        let url = read_url_from_fd(fd, 16 * 1024)
-            .map_err(|e| zbus::fdo::Error::Failed(format!("{e}")))?;
-        let trimmed = url.trim().to_string();
-        if trimmed.is_empty() {
-            return Err(zbus::fdo::Error::Failed("empty URL".to_string()));
-        }
-        if self.verbose >= 1 {
-            // Redact based on config setting; default to redacted
-            let cfg = self.cfg.lock().unwrap();
-            let show = if cfg.notifications.redact_urls {
-                redact_url(&trimmed)
-            } else {
-                trimmed.clone()
-            };
-            eprintln!("[daemon] Received OpenUrlFd: {}", show);
-        }
+    fn with_activity(mut self, activity: Arc<Activity>) -> Self {
+        self.activity = activity;
+        self
+    }
+
+    fn config(&self) -> Arc<Config> {
+        self.cfg.lock().unwrap().clone()
+    }
+
+    /// Log a received URL, redacted unless `notifications.redact_urls` is off.
+    fn log_url(&self, cfg: &Config, method: &str, url: &str) {
+        let shown = crate::notify::shown_url(url, cfg.notifications.redact_urls);
+        log::debug!(target: DBUS, "Received {method}: {shown}");
+    }
+
+    /// Read a URL from `fd` and route it. `sender_pid` identifies the D-Bus caller and is
+    /// used to determine the source application.
+    fn open_url_fd(
+        &self,
+        fd: zbus::zvariant::OwnedFd,
+        sender_pid: Option<u32>,
+        emitter: Option<SignalEmitter<'static>>,
+    ) -> Result<(), MuxieError> {
+        let url = read_request_url(fd).map_err(|e| MuxieError::Failed(e.to_string()))?;
+        self.route_and_open(&url, "OpenUrlFd", sender_pid, emitter)
+    }
+
+    /// Route `url` through the configured patterns and open it. `method` names the D-Bus
+    /// method for log messages.
+    fn route_and_open(
+        &self,
+        url: &str,
+        method: &str,
+        sender_pid: Option<u32>,
+        emitter: Option<SignalEmitter<'static>>,
+    ) -> Result<(), MuxieError> {
+        let cfg = self.config();
+        self.log_url(&cfg, method, url);
         let opener = crate::open::DefaultOpener;
-        let notifier = crate::notify::DefaultNotifier;
-        let cfg_guard = self.cfg.lock().unwrap();
-        let selector = crate::dialog::selector_from_config(&cfg_guard);
+        let notifier = SignalingNotifier { emitter };
+        let selector = crate::dialog::selector_from_config(&cfg);
         match crate::open::open_url_with(
-            &cfg_guard,
+            &cfg,
             &opener,
             &notifier,
             selector.as_ref(),
-            &trimmed,
+            &crate::open::RouteContext::system()
+                .with_source_pid(sender_pid)
+                .with_caller_env(sender_pid),
+            url,
             self.no_notify,
-            self.verbose,
         ) {
             Ok(()) => {
-                if self.verbose >= 1 {
-                    eprintln!("[daemon] Processed OpenUrlFd successfully");
-                }
+                log::debug!(target: DBUS, "Processed {method} successfully");
                 Ok(())
             }
+            Err(e) if e.downcast_ref::<crate::open::Canceled>().is_some() => {
+                log::info!(target: DBUS, "{method}: {e}");
+                Err(MuxieError::Canceled(e.to_string()))
+            }
             Err(e) => {
-                if self.verbose >= 1 {
-                    eprintln!("[daemon] OpenUrlFd failed: {e}");
-                }
-                let es = e.to_string();
-                if es.contains(crate::open::CANCELED_ERR_MARKER) {
-                    return Err(zbus::fdo::Error::Failed(
-                        crate::open::CANCELED_ERR_MARKER.to_string(),
-                    ));
-                }
-                Err(zbus::fdo::Error::Failed(es))
+                log::warn!(target: DBUS, "{method} failed: {e:#}");
+                Err(MuxieError::Failed(format!("{e:#}")))
             }
         }
     }
 
-    #[allow(non_snake_case)]
-    fn ReloadConfig(&self) -> zbus::fdo::Result<bool> {
-        if self.verbose >= 1 {
-            eprintln!("[daemon] Reloading configuration...");
+    /// Read a URL from `fd` and open it with the browser named `browser_name`, bypassing
+    /// pattern routing.
+    fn open_url_with_browser(
+        &self,
+        fd: zbus::zvariant::OwnedFd,
+        browser_name: &str,
+        emitter: Option<SignalEmitter<'static>>,
+    ) -> zbus::fdo::Result<()> {
+        let url = read_request_url(fd).map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
+        let cfg = self.config();
+        self.log_url(&cfg, "OpenUrlWith", &url);
+        let browser = cfg
+            .browsers
+            .iter()
+            .find(|b| b.name == browser_name)
+            .ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!("Unknown browser: {browser_name}"))
+            })?;
+        use crate::notify::Notifier;
+        use crate::open::UrlOpener;
+        let notifier = SignalingNotifier { emitter };
+        match crate::open::DefaultOpener.open(browser, &url) {
+            Ok(()) => {
+                notifier.notify_opened(&url, None, &browser.name);
+                Ok(())
+            }
+            Err(e) => {
+                let prefs = crate::notify::NotifyPrefs {
+                    enabled: cfg.notifications.enabled && !self.no_notify,
+                    redact_urls: cfg.notifications.redact_urls,
+                };
+                notifier.notify_error(
+                    &url,
+                    "explicit browser",
+                    &browser.name,
+                    &e.to_string(),
+                    &prefs,
+                );
+                Err(zbus::fdo::Error::Failed(format!(
+                    "Failed to open {browser_name}: {e}"
+                )))
+            }
         }
-        match read_config() {
-            Ok(new_cfg) => {
-                let mut guard = self.cfg.lock().unwrap();
-                *guard = new_cfg;
-                if self.verbose >= 1 {
-                    eprintln!("[daemon] Reloaded configuration successfully");
-                }
-                Ok(true)
+    }
+
+    /// Re-read the configuration file and apply it if it passes validation.
+    fn reload(&self) -> Result<ConfigDiff> {
+        self.apply_config(read_config()?)
+    }
+
+    /// Swap in `new_cfg` unless non-strict validation reports errors or a pattern names an
+    /// unknown browser, in which case the previous configuration stays active and the
+    /// errors are shown as a notification.
+    fn apply_config(&self, new_cfg: Config) -> Result<ConfigDiff> {
+        let current = self.config();
+        let result = new_cfg.validate(false);
+        for warning in result.warnings() {
+            log::warn!(target: WATCHER, "Configuration warning: {}", warning.message);
+        }
+        let mut errors: Vec<ValidationError> = result.errors().cloned().collect();
+        // Non-strict validation leaves these to `muxie config validate`, but routing to a
+        // browser that does not exist is never what an edit meant
+        errors.extend(new_cfg.unknown_browsers());
+        if !errors.is_empty() {
+            let enabled = current.notifications.enabled && !self.no_notify;
+            crate::notify::notify_config_rejected(&errors, enabled);
+            return Err(InvalidConfig(errors).into());
+        }
+        let diff = ConfigDiff::between(&current, &new_cfg);
+        if new_cfg.logging != current.logging {
+            crate::logging::reconfigure(&new_cfg.logging);
+        }
+        *self.cfg.lock().unwrap() = Arc::new(new_cfg);
+        self.last_reload.store(unix_now(), Ordering::SeqCst);
+        log::info!(target: WATCHER, "Configuration changes: {diff}");
+        Ok(diff)
+    }
+
+    /// Dry-run routing of `url`: the browser that would be used (the first one offered when
+    /// a selection dialog would be shown), the pattern that selected it (empty for the
+    /// default browser) and the command line that would be run.
+    fn route_url(
+        &self,
+        url: &str,
+        sender_pid: Option<u32>,
+    ) -> zbus::fdo::Result<(String, String, Vec<String>)> {
+        let cfg = self.config();
+        let ctx = crate::open::RouteContext::system()
+            .with_source_pid(sender_pid)
+            .with_caller_env(sender_pid);
+        let report = crate::route::explain(&cfg, &ctx, url);
+        let (entry, candidates) = report
+            .selected()
+            .ok_or_else(|| zbus::fdo::Error::Failed("No browsers configured".to_string()))?;
+        let browser = candidates.browsers[0];
+        Ok((
+            browser.name.clone(),
+            entry.map(|e| e.pattern.clone()).unwrap_or_default(),
+            browser.command_line(url),
+        ))
+    }
+
+    fn list_browsers(&self) -> Vec<(String, String, Vec<String>)> {
+        self.config()
+            .browsers
+            .iter()
+            .map(|b| (b.name.clone(), b.executable.clone(), b.args.clone()))
+            .collect()
+    }
+
+    fn list_patterns(&self) -> Vec<(String, Vec<String>)> {
+        self.config()
+            .patterns
+            .iter()
+            .map(|p| (p.pattern.clone(), p.browsers.clone()))
+            .collect()
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A changed configuration file failed validation and was not applied.
+#[derive(Debug)]
+struct InvalidConfig(Vec<ValidationError>);
+
+impl std::fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors: Vec<String> = self
+            .0
+            .iter()
+            .map(|e| format!("{}: {}", e.code, e.message))
+            .collect();
+        write!(
+            f,
+            "kept previous configuration, {} validation error(s): {}",
+            self.0.len(),
+            errors.join("; ")
+        )
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+/// `error_count` for `ConfigReloaded`: the number of validation errors, or 1 if the
+/// file could not be read.
+fn reload_error_count(err: &anyhow::Error) -> u32 {
+    err.downcast_ref::<InvalidConfig>()
+        .map_or(1, |invalid| invalid.0.len() as u32)
+}
+
+/// Emit `ConfigReloaded` and, on success, the changed properties. Used by the config
+/// watcher, which runs outside of a method call.
+async fn announce_reload(conn: &zbus::Connection, success: bool, error_count: u32) {
+    let Ok(iface) = conn
+        .object_server()
+        .interface::<_, MuxieDaemon>(DBUS_PATH)
+        .await
+    else {
+        return;
+    };
+    let emitter = iface.signal_emitter();
+    let _ = MuxieDaemon::config_reloaded(emitter, success, error_count).await;
+    if success {
+        let daemon = iface.get().await;
+        let _ = daemon.emit_config_properties_changed(emitter).await;
+    }
+}
+
+/// Notifier used by the daemon: desktop notifications as usual, plus `UrlOpened` and
+/// `OpenFailed` signals. Signals always carry redacted URLs since any client on the
+/// session bus can receive them.
+struct SignalingNotifier {
+    emitter: Option<SignalEmitter<'static>>,
+}
+
+impl crate::notify::Notifier for SignalingNotifier {
+    fn notify_error(
+        &self,
+        url: &str,
+        rule: &str,
+        browser: &str,
+        error_summary: &str,
+        prefs: &crate::notify::NotifyPrefs,
+    ) {
+        crate::notify::notify_error(url, rule, browser, error_summary, prefs);
+        if let Some(emitter) = &self.emitter {
+            let redacted = redact_url(url);
+            // Routing errors are not meant to quote the URL, but make sure it never leaks
+            let error = error_summary.replace(url, &redacted);
+            let _ = async_io::block_on(MuxieDaemon::open_failed(
+                emitter, &redacted, browser, &error,
+            ));
+        }
+    }
+
+    fn notify_skipped(
+        &self,
+        url: &str,
+        rule: &str,
+        reason: &str,
+        prefs: &crate::notify::NotifyPrefs,
+    ) {
+        crate::notify::notify_skipped(url, rule, reason, prefs);
+    }
+
+    fn notify_opened(&self, url: &str, rule: Option<&str>, browser: &str) {
+        if let Some(emitter) = &self.emitter {
+            let _ = async_io::block_on(MuxieDaemon::url_opened(
+                emitter,
+                &redact_url(url),
+                rule.unwrap_or_default(),
+                browser,
+            ));
+        }
+    }
+}
+
+/// Strict validation of the configuration file on disk as `(code, message, path)` tuples.
+/// Only errors are returned. A file that cannot be read or parsed yields a single
+/// `config.read` entry.
+fn validate_config_file() -> Vec<(String, String, String)> {
+    match read_config() {
+        Ok(cfg) => cfg
+            .validate(true)
+            .errors()
+            .map(|e| {
+                (
+                    e.code.clone(),
+                    e.message.clone(),
+                    e.path.clone().unwrap_or_default(),
+                )
+            })
+            .collect(),
+        Err(e) => vec![("config.read".to_string(), format!("{e:#}"), String::new())],
+    }
+}
+
+#[interface(name = "xyz.adf.Muxie1")]
+impl MuxieDaemon {
+    #[allow(non_snake_case)]
+    async fn OpenUrlFd(
+        &self,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        fd: zbus::zvariant::OwnedFd,
+    ) -> Result<(), MuxieError> {
+        let _busy = self.activity.begin();
+        let sender_pid = match header.sender() {
+            Some(sender) => sender_pid(conn, sender).await,
+            None => None,
+        };
+        // Routing may block on a selection dialog; run it off the D-Bus executor so other
+        // requests are served (and can show their own dialogs) in the meantime.
+        let daemon = self.clone();
+        let emitter = emitter.to_owned();
+        blocking::unblock(move || daemon.open_url_fd(fd, sender_pid, Some(emitter))).await
+    }
+
+    /// Open the URL read from `fd` with a configured browser, without pattern routing.
+    #[allow(non_snake_case)]
+    async fn OpenUrlWith(
+        &self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        fd: zbus::zvariant::OwnedFd,
+        browser_name: String,
+    ) -> zbus::fdo::Result<()> {
+        let _busy = self.activity.begin();
+        let daemon = self.clone();
+        let emitter = emitter.to_owned();
+        blocking::unblock(move || daemon.open_url_with_browser(fd, &browser_name, Some(emitter)))
+            .await
+    }
+
+    /// Show how `url` would be routed: `(browser, pattern, argv)`.
+    #[allow(non_snake_case)]
+    async fn Route(
+        &self,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+        url: String,
+    ) -> zbus::fdo::Result<(String, String, Vec<String>)> {
+        let _busy = self.activity.begin();
+        let sender_pid = match header.sender() {
+            Some(sender) => sender_pid(conn, sender).await,
+            None => None,
+        };
+        // Network conditions may query NetworkManager synchronously
+        let daemon = self.clone();
+        blocking::unblock(move || daemon.route_url(url.trim(), sender_pid)).await
+    }
+
+    /// Configured browsers as `(name, executable, args)`.
+    #[allow(non_snake_case)]
+    fn ListBrowsers(&self) -> Vec<(String, String, Vec<String>)> {
+        let _busy = self.activity.begin();
+        self.list_browsers()
+    }
+
+    /// Configured patterns as `(pattern, browsers)`.
+    #[allow(non_snake_case)]
+    fn ListPatterns(&self) -> Vec<(String, Vec<String>)> {
+        let _busy = self.activity.begin();
+        self.list_patterns()
+    }
+
+    #[allow(non_snake_case)]
+    fn GetConfigPath(&self) -> String {
+        let _busy = self.activity.begin();
+        crate::paths::config_path().to_string_lossy().into_owned()
+    }
+
+    /// Validate the configuration file on disk (including edits not yet reloaded).
+    #[allow(non_snake_case)]
+    async fn Validate(&self) -> Vec<(String, String, String)> {
+        let _busy = self.activity.begin();
+        blocking::unblock(validate_config_file).await
+    }
+
+    #[allow(non_snake_case)]
+    async fn ReloadConfig(
+        &self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<bool> {
+        let _busy = self.activity.begin();
+        Ok(self.reload_and_announce(&emitter).await.is_ok())
+    }
+
+    /// `version` of the loaded configuration.
+    #[zbus(property)]
+    fn config_version(&self) -> u32 {
+        self.config().version
+    }
+
+    /// Unix time (seconds) of the last successful configuration load.
+    #[zbus(property)]
+    fn last_reload_time(&self) -> u64 {
+        self.last_reload.load(Ordering::SeqCst)
+    }
+
+    #[zbus(property)]
+    fn pattern_count(&self) -> u32 {
+        self.config().patterns.len() as u32
+    }
+
+    /// A URL was handed to `browser`; `pattern` is empty for the default browser.
+    #[zbus(signal)]
+    async fn url_opened(
+        emitter: &SignalEmitter<'_>,
+        redacted_url: &str,
+        pattern: &str,
+        browser: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn open_failed(
+        emitter: &SignalEmitter<'_>,
+        redacted_url: &str,
+        browser: &str,
+        error: &str,
+    ) -> zbus::Result<()>;
+
+    /// On failure the previous configuration stays active; `error_count` is the number of
+    /// validation errors in the rejected file, or 1 if it could not be read.
+    #[zbus(signal)]
+    async fn config_reloaded(
+        emitter: &SignalEmitter<'_>,
+        success: bool,
+        error_count: u32,
+    ) -> zbus::Result<()>;
+}
+
+impl MuxieDaemon {
+    /// Reload the configuration for a D-Bus request and announce the outcome with
+    /// `ConfigReloaded` and, on success, the changed properties.
+    async fn reload_and_announce(&self, emitter: &SignalEmitter<'_>) -> Result<ConfigDiff> {
+        log::debug!(target: DBUS, "Reloading configuration");
+        let result = self.reload();
+        match &result {
+            Ok(_) => {
+                log::info!(target: DBUS, "Reloaded configuration");
+                let _ = Self::config_reloaded(emitter, true, 0).await;
+                let _ = self.emit_config_properties_changed(emitter).await;
             }
             Err(e) => {
-                if self.verbose >= 1 {
-                    eprintln!("[daemon] Reload failed: {e}");
+                log::warn!(target: DBUS, "Reload failed: {e}");
+                let _ = Self::config_reloaded(emitter, false, reload_error_count(e)).await;
+            }
+        }
+        result
+    }
+
+    async fn emit_config_properties_changed(
+        &self,
+        emitter: &SignalEmitter<'_>,
+    ) -> zbus::Result<()> {
+        self.config_version_changed(emitter).await?;
+        self.last_reload_time_changed(emitter).await?;
+        self.pattern_count_changed(emitter).await
+    }
+}
+
+/// `org.freedesktop.Application` on the daemon object, so launchers can hand URLs over
+/// D-Bus (`DBusActivatable=true` in the desktop entry) instead of running `muxie open`.
+struct FdoApplication {
+    daemon: MuxieDaemon,
+}
+
+impl FdoApplication {
+    /// Open each URI in turn. A canceled selection dialog is not an error for the
+    /// launcher; the first other failure is reported after all URIs were tried.
+    fn open_uris(
+        &self,
+        uris: &[String],
+        sender_pid: Option<u32>,
+        emitter: Option<SignalEmitter<'static>>,
+    ) -> Result<(), MuxieError> {
+        let mut first_err = None;
+        for uri in uris.iter().map(|u| u.trim()).filter(|u| !u.is_empty()) {
+            match self
+                .daemon
+                .route_and_open(uri, "Open", sender_pid, emitter.clone())
+            {
+                Err(MuxieError::Canceled(_)) => {}
+                Err(e) => {
+                    first_err.get_or_insert(e);
                 }
-                Ok(false)
+                Ok(()) => {}
             }
         }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    async fn run_action(
+        &self,
+        action_name: &str,
+        emitter: &SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        match action_name {
+            "reload" => self
+                .daemon
+                .reload_and_announce(emitter)
+                .await
+                .map(|_| ())
+                .map_err(|e| zbus::fdo::Error::Failed(format!("Reload failed: {e}"))),
+            _ => Err(zbus::fdo::Error::NotSupported(format!(
+                "Unknown action: {action_name}"
+            ))),
+        }
     }
 }
 
+type PlatformData = std::collections::HashMap<String, zbus::zvariant::OwnedValue>;
+
+#[interface(name = "org.freedesktop.Application")]
+impl FdoApplication {
+    /// Muxie has no window to show; activation only starts the daemon.
+    fn activate(&self, _platform_data: PlatformData) {
+        let _busy = self.daemon.activity.begin();
+    }
+
+    async fn open(
+        &self,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        uris: Vec<String>,
+        _platform_data: PlatformData,
+    ) -> Result<(), MuxieError> {
+        let _busy = self.daemon.activity.begin();
+        let sender_pid = match header.sender() {
+            Some(sender) => sender_pid(conn, sender).await,
+            None => None,
+        };
+        let app = FdoApplication {
+            daemon: self.daemon.clone(),
+        };
+        let emitter = emitter.to_owned();
+        blocking::unblock(move || app.open_uris(&uris, sender_pid, Some(emitter))).await
+    }
+
+    /// Supported actions: `reload` re-reads the configuration file.
+    async fn activate_action(
+        &self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        action_name: String,
+        _parameter: Vec<zbus::zvariant::OwnedValue>,
+        _platform_data: PlatformData,
+    ) -> zbus::fdo::Result<()> {
+        let _busy = self.daemon.activity.begin();
+        self.run_action(&action_name, &emitter).await
+    }
+}
+
+/// Resolve the process ID behind a D-Bus unique name.
+async fn sender_pid(conn: &zbus::Connection, sender: &zbus_names::UniqueName<'_>) -> Option<u32> {
+    let proxy = zbus::fdo::DBusProxy::new(conn).await.ok()?;
+    proxy
+        .get_connection_unix_process_id(sender.clone().into())
+        .await
+        .ok()
+}
+
+/// Read and trim the URL sent over `fd`, rejecting empty input.
+fn read_request_url(fd: zbus::zvariant::OwnedFd) -> Result<String> {
+    let url = read_url_from_fd(fd, 16 * 1024)?;
+    let trimmed = url.trim().to_string();
+    if trimmed.is_empty() {
+        anyhow::bail!("empty URL");
+    }
+    Ok(trimmed)
+}
+
 fn read_url_from_fd(fd: zbus::zvariant::OwnedFd, cap: usize) -> anyhow::Result<String> {
     use std::io::Read;
     // Convert to std OwnedFd, then to File
@@ -132,68 +676,359 @@ fn read_url_from_fd(fd: zbus::zvariant::OwnedFd, cap: usize) -> anyhow::Result<S
     Ok(s)
 }
 
-/// Run the Muxie daemon
-pub fn run(no_notify: bool, verbose: u8) -> Result<()> {
+/// Run the Muxie daemon until it receives SIGTERM/SIGINT or, with
+/// `daemon.idle_exit_secs`, until it has been idle for that long.
+pub fn run(no_notify: bool) -> Result<()> {
+    async_io::block_on(serve(no_notify))
+}
+
+async fn serve(no_notify: bool) -> Result<()> {
     let cfg = read_config().context("Failed to read configuration at startup")?;
-    let cfg_arc = Arc::new(Mutex::new(cfg));
-    let daemon = MuxieDaemon::new(cfg_arc.clone(), no_notify, verbose);
-
-    // Build a blocking zbus connection, own the well-known name, and export the object
-    let conn =
-        zbus::blocking::Connection::session().context("Failed to connect to session D-Bus")?;
-    let name = zbus_names::WellKnownName::try_from(DBUS_SERVICE).context("Invalid service name")?;
-    conn.request_name(name)
-        .context(format!("Failed to own D-Bus name {DBUS_SERVICE}"))?;
-    let server = conn.object_server();
-    server
-        .at(DBUS_PATH, daemon)
-        .context(format!("Failed to export daemon object at {DBUS_PATH}"))?;
+    let socket_enabled = cfg.daemon.socket;
+    let cfg_arc: SharedConfig = Arc::new(Mutex::new(Arc::new(cfg)));
+    let activity = Arc::new(Activity::new());
+    let daemon = MuxieDaemon::new(cfg_arc.clone(), no_notify).with_activity(activity.clone());
 
-    if verbose >= 1 {
-        eprintln!(
-            "[daemon] Started. Service={DBUS_SERVICE}, Object={DBUS_PATH}, Interface={DBUS_INTERFACE}"
+    // Install handlers before owning the name so an early SIGTERM still shuts down cleanly
+    let mut signals =
+        Signals::new([Signal::Term, Signal::Int]).context("Failed to install signal handlers")?;
+
+    // Without a session bus the daemon can still serve its socket
+    let conn = match export_dbus(&daemon).await {
+        Ok(conn) => Some(conn),
+        Err(e) if socket_enabled => {
+            log::warn!(target: DAEMON, "D-Bus unavailable, serving the socket only: {e:#}");
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    if conn.is_some() {
+        log::info!(
+            target: DAEMON,
+            "Started. Service={}, Object={DBUS_PATH}, Interface={DBUS_INTERFACE}",
+            bus_name()
         );
     }
 
+    let socket = match socket_enabled.then(|| start_socket(&daemon, conn.clone())) {
+        Some(Ok(server)) => Some(server),
+        Some(Err(e)) if conn.is_some() => {
+            log::warn!(target: DAEMON, "Failed to start socket listener: {e:#}");
+            None
+        }
+        Some(Err(e)) => return Err(e),
+        None => None,
+    };
+
     // Start auto-reload watcher in the background
-    if let Err(e) = start_config_watcher(cfg_arc, verbose) {
-        eprintln!("[daemon] Warning: failed to start config watcher: {e}");
+    let watcher = match start_config_watcher(daemon.clone(), conn.clone()) {
+        Ok(w) => Some(w),
+        Err(e) => {
+            log::warn!(target: WATCHER, "Failed to start config watcher: {e}");
+            None
+        }
+    };
+
+    let reason = future::or(
+        async {
+            match signals.next().await {
+                Some(Ok(sig)) => format!("received {sig:?}"),
+                _ => "signal handler closed".to_string(),
+            }
+        },
+        async {
+            wait_idle(&cfg_arc, &activity).await;
+            "idle timeout".to_string()
+        },
+    )
+    .await;
+    log::info!(target: DAEMON, "Shutting down: {reason}");
+
+    // Stop taking new requests; D-Bus activation starts a fresh daemon for the next one
+    if let Some(conn) = &conn {
+        let _ = conn.release_name(bus_name()).await;
     }
+    drop(socket);
+    // Calls in progress get a grace period; a second signal cuts it short
+    let drained = future::or(drain(&activity, SHUTDOWN_GRACE), async {
+        match signals.next().await {
+            Some(_) => false,
+            None => future::pending().await,
+        }
+    })
+    .await;
+    if !drained {
+        log::warn!(
+            target: DAEMON,
+            "Exiting with {} call(s) still in progress",
+            activity.in_flight()
+        );
+    }
+    if let Some(w) = watcher {
+        w.stop();
+    }
+    Ok(())
+}
+
+/// Connect to the session bus, export the daemon objects and own the service name.
+async fn export_dbus(daemon: &MuxieDaemon) -> Result<zbus::Connection> {
+    let conn = zbus::Connection::session()
+        .await
+        .context("Failed to connect to session D-Bus")?;
+    conn.object_server()
+        .at(
+            DBUS_PATH,
+            FdoApplication {
+                daemon: daemon.clone(),
+            },
+        )
+        .await
+        .context(format!(
+            "Failed to export org.freedesktop.Application at {DBUS_PATH}"
+        ))?;
+    conn.object_server()
+        .at(DBUS_PATH, daemon.clone())
+        .await
+        .context(format!("Failed to export daemon object at {DBUS_PATH}"))?;
+    let name = bus_name();
+    conn.request_name(name.as_str())
+        .await
+        .context(format!("Failed to own D-Bus name {name}"))?;
+    Ok(conn)
+}
+
+fn start_socket(daemon: &MuxieDaemon, conn: Option<zbus::Connection>) -> Result<SocketServer> {
+    let path = crate::paths::socket_path().context("XDG_RUNTIME_DIR is not set")?;
+    let server = SocketServer::bind(&path)?;
+    server.spawn(Arc::new(SocketHandler {
+        daemon: daemon.clone(),
+        conn,
+    }))?;
+    log::info!(target: DAEMON, "Listening on {}", server.path().display());
+    Ok(server)
+}
 
-    // Block the main thread; zbus runs the object server internally.
-    // We keep the process alive until killed by the user.
+/// Serves socket requests from the same state as the D-Bus interface. `conn` is used to
+/// emit the usual D-Bus signals when a session bus is available.
+struct SocketHandler {
+    daemon: MuxieDaemon,
+    conn: Option<zbus::Connection>,
+}
+
+impl crate::socket::Handler for SocketHandler {
+    fn handle(&self, req: Request, peer_pid: Option<u32>) -> Response {
+        use crate::socket::{ERROR_CANCELED, ERROR_FAILED, ERROR_INVALID};
+        let _busy = self.daemon.activity.begin();
+        match req {
+            Request::Open { url } => {
+                let url = url.trim();
+                if url.is_empty() {
+                    return Response::error(ERROR_INVALID, "empty URL");
+                }
+                let emitter = self
+                    .conn
+                    .as_ref()
+                    .and_then(|conn| SignalEmitter::new(conn, DBUS_PATH).ok())
+                    .map(|e| e.to_owned());
+                match self
+                    .daemon
+                    .route_and_open(url, "socket open", peer_pid, emitter)
+                {
+                    Ok(()) => Response::success(),
+                    Err(MuxieError::Canceled(msg)) => Response::error(ERROR_CANCELED, msg),
+                    Err(MuxieError::Failed(msg)) => Response::error(ERROR_FAILED, msg),
+                    Err(e) => Response::error(ERROR_FAILED, e.to_string()),
+                }
+            }
+            Request::Route { url } => match self.daemon.route_url(url.trim(), peer_pid) {
+                Ok((browser, pattern, argv)) => Response {
+                    route: Some(RouteInfo {
+                        browser,
+                        pattern: (!pattern.is_empty()).then_some(pattern),
+                        argv,
+                    }),
+                    ..Response::success()
+                },
+                Err(zbus::fdo::Error::Failed(msg)) => Response::error(ERROR_FAILED, msg),
+                Err(e) => Response::error(ERROR_FAILED, e.to_string()),
+            },
+            Request::Reload => {
+                let res = self.daemon.reload();
+                if let Some(conn) = &self.conn {
+                    let error_count = res.as_ref().err().map_or(0, reload_error_count);
+                    async_io::block_on(announce_reload(conn, res.is_ok(), error_count));
+                }
+                match res {
+                    Ok(_) => Response::success(),
+                    Err(e) => Response::error(ERROR_FAILED, format!("{e:#}")),
+                }
+            }
+            Request::Status => {
+                let cfg = self.daemon.config();
+                Response {
+                    status: Some(Status {
+                        pid: std::process::id(),
+                        config_path: crate::paths::config_path().to_string_lossy().into_owned(),
+                        config_version: cfg.version,
+                        pattern_count: cfg.patterns.len(),
+                        last_reload: self.daemon.last_reload.load(Ordering::SeqCst),
+                        dbus: self.conn.is_some(),
+                    }),
+                    ..Response::success()
+                }
+            }
+        }
+    }
+}
+
+/// Resolve once the daemon has been idle for `daemon.idle_exit_secs`. The setting is
+/// re-read on every check so configuration reloads take effect.
+async fn wait_idle(cfg: &SharedConfig, activity: &Activity) {
+    const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(60);
     loop {
-        std::thread::park();
+        let limit = cfg.lock().unwrap().daemon.idle_exit_secs;
+        let wait = match activity.idle_for() {
+            _ if limit == 0 => MAX_CHECK_INTERVAL,
+            Some(idle) if idle >= Duration::from_secs(limit) => return,
+            Some(idle) => (Duration::from_secs(limit) - idle).min(MAX_CHECK_INTERVAL),
+            None => MAX_CHECK_INTERVAL,
+        };
+        async_io::Timer::after(wait).await;
+    }
+}
+
+/// How long shutdown waits for calls in progress, such as an open selection dialog.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Wait until no calls are in progress, for at most `grace`. Returns whether they all
+/// finished.
+async fn drain(activity: &Activity, grace: Duration) -> bool {
+    let deadline = Instant::now() + grace;
+    while activity.in_flight() > 0 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        async_io::Timer::after(Duration::from_millis(100)).await;
+    }
+    true
+}
+
+/// Tracks D-Bus calls for the idle timeout.
+struct Activity {
+    last: Mutex<Instant>,
+    in_flight: AtomicUsize,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Activity {
+            last: Mutex::new(Instant::now()),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Mark a call as in progress until the returned guard is dropped.
+    fn begin(self: &Arc<Self>) -> ActivityGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        ActivityGuard(self.clone())
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Time since the last call finished, or `None` while a call is in progress.
+    fn idle_for(&self) -> Option<Duration> {
+        if self.in_flight() > 0 {
+            return None;
+        }
+        Some(self.last.lock().unwrap().elapsed())
     }
 }
 
-fn start_config_watcher(cfg: Arc<Mutex<Config>>, verbose: u8) -> Result<()> {
+struct ActivityGuard(Arc<Activity>);
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        *self.0.last.lock().unwrap() = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+enum WatchMsg {
+    Event(std::result::Result<::notify::Event, ::notify::Error>),
+    Stop,
+}
+
+/// Background config watcher; `stop` shuts it down and joins its thread.
+struct ConfigWatcher {
+    tx: std::sync::mpsc::Sender<WatchMsg>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+impl ConfigWatcher {
+    fn stop(self) {
+        let _ = self.tx.send(WatchMsg::Stop);
+        let _ = self.thread.join();
+    }
+}
+
+/// Watch the directories in `dirs` that are not in `watched` yet and stop watching the
+/// ones that are no longer needed. Returns the directories now being watched.
+fn update_watches(
+    watcher: &mut ::notify::RecommendedWatcher,
+    watched: BTreeSet<PathBuf>,
+    dirs: BTreeSet<PathBuf>,
+) -> BTreeSet<PathBuf> {
+    use ::notify::{RecursiveMode, Watcher};
+    for dir in watched.difference(&dirs) {
+        let _ = watcher.unwatch(dir);
+    }
+    let mut now = watched
+        .intersection(&dirs)
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    for dir in dirs.difference(&watched) {
+        match watcher.watch(dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                log::debug!(target: WATCHER, "Watching config directory: {}", dir.display());
+                now.insert(dir.clone());
+            }
+            Err(e) => log::warn!(target: WATCHER, "Cannot watch {}: {e}", dir.display()),
+        }
+    }
+    now
+}
+
+fn start_config_watcher(
+    daemon: MuxieDaemon,
+    conn: Option<zbus::Connection>,
+) -> Result<ConfigWatcher> {
     let cfg_path = crate::paths::config_path();
-    let parent: PathBuf = cfg_path
-        .parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| PathBuf::from("."));
+    let system = crate::paths::system_config_paths();
 
-    let (tx, rx) = std::sync::mpsc::channel::<Result<::notify::Event, ::notify::Error>>();
+    let (tx, rx) = std::sync::mpsc::channel::<WatchMsg>();
+    let event_tx = tx.clone();
     let mut watcher = ::notify::recommended_watcher(move |res| {
-        let _ = tx.send(res);
+        let _ = event_tx.send(WatchMsg::Event(res));
     })?;
-    use ::notify::{RecursiveMode, Watcher};
-    watcher.watch(&parent, RecursiveMode::NonRecursive)?;
-
-    if verbose >= 1 {
-        eprintln!("[daemon] Watching config directory: {}", parent.display());
+    let mut watch = WatchSet::for_config(&cfg_path, &system);
+    let mut watched = update_watches(&mut watcher, BTreeSet::new(), watch.dirs());
+    if watched.is_empty() {
+        anyhow::bail!("No configuration directory to watch");
     }
 
-    std::thread::spawn(move || {
+    let thread = std::thread::spawn(move || {
         let debounce = Duration::from_millis(400);
         let max_interval = Duration::from_secs(2);
         let target_name = cfg_path.file_name().map(|s| s.to_owned());
 
         loop {
-            let Ok(res) = rx.recv() else { break };
+            let Ok(WatchMsg::Event(res)) = rx.recv() else {
+                break;
+            };
             let Ok(event) = res else { continue };
-            if !event_is_relevant(&event, &cfg_path, target_name.as_deref()) {
+            if !event_is_relevant(&event, &watch, &cfg_path, target_name.as_deref()) {
                 continue;
             }
             let mut last_relevant = Instant::now();
@@ -201,51 +1036,54 @@ fn start_config_watcher(cfg: Arc<Mutex<Config>>, verbose: u8) -> Result<()> {
             // Coalesce until quiet period or max interval
             loop {
                 match rx.recv_timeout(Duration::from_millis(150)) {
-                    Ok(Ok(ev)) => {
-                        if event_is_relevant(&ev, &cfg_path, target_name.as_deref()) {
+                    Ok(WatchMsg::Event(Ok(ev))) => {
+                        if event_is_relevant(&ev, &watch, &cfg_path, target_name.as_deref()) {
                             last_relevant = Instant::now();
                         }
                     }
-                    Ok(Err(_)) => {}
-                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                    Ok(WatchMsg::Event(Err(_))) => {}
+                    Ok(WatchMsg::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                    Err(RecvTimeoutError::Timeout) => {
                         if last_relevant.elapsed() >= debounce || start.elapsed() >= max_interval {
                             break;
                         }
                     }
-                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
                 }
             }
             // Attempt reload (best effort)
-            match read_config() {
-                Ok(new_cfg) => {
-                    if let Ok(mut guard) = cfg.lock() {
-                        *guard = new_cfg;
-                        if verbose >= 1 {
-                            eprintln!("[daemon] Auto-reload: configuration updated");
-                        }
-                    }
+            let (success, error_count) = match daemon.reload() {
+                Ok(_) => {
+                    log::info!(target: WATCHER, "Auto-reload: configuration updated");
+                    (true, 0)
                 }
                 Err(e) => {
-                    if verbose >= 1 {
-                        eprintln!("[daemon] Auto-reload failed: {e}");
-                    }
+                    log::warn!(target: WATCHER, "Auto-reload failed: {e}");
+                    (false, reload_error_count(&e))
                 }
+            };
+            if let Some(conn) = &conn {
+                async_io::block_on(announce_reload(conn, success, error_count));
             }
+            // The set of included files may have changed
+            watch = WatchSet::for_config(&cfg_path, &system);
+            watched = update_watches(&mut watcher, watched, watch.dirs());
         }
     });
 
-    // Keep watcher alive by preventing drop
-    std::mem::forget(watcher);
-    Ok(())
+    Ok(ConfigWatcher { tx, thread })
 }
 
+/// Whether `ev` touches the configuration file, an included file or a drop-in
+/// directory. The main file is also recognized by name, e.g. when its directory is
+/// reached through a symlink.
 fn event_is_relevant(
     ev: &::notify::Event,
+    watch: &WatchSet,
     cfg_path: &std::path::Path,
     target_name: Option<&std::ffi::OsStr>,
 ) -> bool {
     for p in &ev.paths {
-        if p == cfg_path {
+        if p == cfg_path || watch.is_relevant(p) {
             return true;
         }
         if let (Some(tn), Some(pn)) = (target_name, p.file_name())
@@ -267,14 +1105,13 @@ mod tests {
             version: 1,
             browsers: vec![],
             patterns: vec![],
-            notifications: crate::config::Notifications::default(),
-            dialog: crate::config::DialogOptions::default(),
+            ..Default::default()
         }
     }
 
     #[test]
     fn open_url_rejects_empty() {
-        let d = MuxieDaemon::new(Arc::new(Mutex::new(cfg_empty())), false, 0);
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_empty()))), false);
         // Create pipe with spaces
         let mut fds = [0 as libc::c_int; 2];
         assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
@@ -286,13 +1123,13 @@ mod tests {
         drop(w);
         let std_owned = unsafe { std::os::fd::OwnedFd::from_raw_fd(rfd) };
         let zfd = zbus::zvariant::OwnedFd::from(std_owned);
-        let res = d.OpenUrlFd(zfd);
+        let res = d.open_url_fd(zfd, None, None);
         assert!(res.is_err());
     }
 
     #[test]
     fn open_url_fd_propagates_error_on_invalid_cfg() {
-        let d = MuxieDaemon::new(Arc::new(Mutex::new(cfg_empty())), false, 0);
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_empty()))), false);
         let mut fds = [0 as libc::c_int; 2];
         assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
         let rfd = fds[0];
@@ -303,7 +1140,381 @@ mod tests {
         drop(w);
         let std_owned = unsafe { std::os::fd::OwnedFd::from_raw_fd(rfd) };
         let zfd = zbus::zvariant::OwnedFd::from(std_owned);
-        let res = d.OpenUrlFd(zfd);
+        let res = d.open_url_fd(zfd, None, None);
         assert!(res.is_err());
     }
+
+    fn pipe_with(content: &str) -> zbus::zvariant::OwnedFd {
+        let mut fds = [0 as libc::c_int; 2];
+        assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        let mut w = unsafe { std::fs::File::from_raw_fd(fds[1]) };
+        use std::io::Write;
+        write!(w, "{content}").unwrap();
+        drop(w);
+        let std_owned = unsafe { std::os::fd::OwnedFd::from_raw_fd(fds[0]) };
+        zbus::zvariant::OwnedFd::from(std_owned)
+    }
+
+    fn cfg_routing() -> Config {
+        let mut cfg = cfg_empty();
+        cfg.browsers = vec![
+            crate::browser::Browser {
+                name: "Firefox".into(),
+                executable: "firefox".into(),
+                args: vec!["%u".into()],
+                locked: false,
+            },
+            crate::browser::Browser {
+                name: "Work".into(),
+                executable: "chromium".into(),
+                args: vec!["--profile-directory=Work".into()],
+                locked: false,
+            },
+        ];
+        cfg.patterns = vec![crate::config::PatternEntry {
+            pattern: "work.com".into(),
+            browsers: vec!["Work".into()],
+            ..Default::default()
+        }];
+        cfg
+    }
+
+    #[test]
+    fn route_reports_browser_pattern_and_argv() {
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_routing()))), false);
+        let (browser, pattern, argv) = d.route_url("https://git.work.com/x", None).unwrap();
+        assert_eq!(browser, "Work");
+        assert_eq!(pattern, "work.com");
+        assert_eq!(
+            argv,
+            [
+                "chromium",
+                "--profile-directory=Work",
+                "https://git.work.com/x"
+            ]
+        );
+
+        let (browser, pattern, _) = d.route_url("https://example.com", None).unwrap();
+        assert_eq!(browser, "Firefox");
+        assert_eq!(pattern, "");
+    }
+
+    #[test]
+    fn lists_browsers_and_patterns() {
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_routing()))), false);
+        let browsers = d.list_browsers();
+        assert_eq!(browsers.len(), 2);
+        assert_eq!(
+            browsers[0],
+            (
+                "Firefox".to_string(),
+                "firefox".to_string(),
+                vec!["%u".to_string()]
+            )
+        );
+        assert_eq!(
+            d.list_patterns(),
+            vec![("work.com".to_string(), vec!["Work".to_string()])]
+        );
+    }
+
+    #[test]
+    fn open_url_with_rejects_unknown_browser() {
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_routing()))), false);
+        let res = d.open_url_with_browser(pipe_with("https://example.com"), "Nope", None);
+        assert!(matches!(res, Err(zbus::fdo::Error::InvalidArgs(_))));
+    }
+
+    #[test]
+    fn application_open_skips_empty_and_reports_failures() {
+        let app = FdoApplication {
+            daemon: MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_empty()))), false),
+        };
+        assert!(app.open_uris(&[" ".to_string()], None, None).is_ok());
+        // No browsers configured
+        assert!(
+            app.open_uris(&["https://example.com".to_string()], None, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn application_reload_action_announces_reload() {
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_empty()))), true);
+        let app = FdoApplication { daemon: d.clone() };
+        let (server, client) = serve_p2p(d);
+        let mut stream = zbus::MessageStream::from(&client);
+        let emitter = SignalEmitter::new(&server, DBUS_PATH).unwrap();
+        assert!(matches!(
+            async_io::block_on(app.run_action("nope", &emitter)),
+            Err(zbus::fdo::Error::NotSupported(_))
+        ));
+        // Whether or not the configuration file can be read here, the outcome is signaled
+        let _ = async_io::block_on(app.run_action("reload", &emitter));
+        let (member, _) = next_signal(&mut stream);
+        assert_eq!(member, "ConfigReloaded");
+    }
+
+    #[test]
+    fn socket_handler_routes_and_reports_status() {
+        use crate::socket::Handler;
+        let handler = SocketHandler {
+            daemon: MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_routing()))), false),
+            conn: None,
+        };
+        let resp = handler.handle(
+            Request::Route {
+                url: "https://git.work.com/x".into(),
+            },
+            None,
+        );
+        let route = resp.route.unwrap();
+        assert_eq!(route.browser, "Work");
+        assert_eq!(route.pattern.as_deref(), Some("work.com"));
+
+        let resp = handler.handle(Request::Open { url: " ".into() }, None);
+        assert_eq!(resp.error.as_deref(), Some(crate::socket::ERROR_INVALID));
+
+        let status = handler.handle(Request::Status, None).status.unwrap();
+        assert_eq!(status.pattern_count, 1);
+        assert!(!status.dbus);
+        assert_eq!(handler.daemon.activity.in_flight(), 0);
+    }
+
+    #[test]
+    fn invalid_config_is_not_applied() {
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_routing()))), true);
+        let mut broken = cfg_routing();
+        broken.browsers[0].name = "Work".into();
+        let err = d.apply_config(broken).unwrap_err();
+        assert_eq!(reload_error_count(&err), 1);
+        assert!(err.to_string().starts_with("kept previous configuration"));
+        assert_eq!(d.config().browsers[0].name, "Firefox");
+
+        let mut updated = cfg_routing();
+        updated.browsers.remove(0);
+        let diff = d.apply_config(updated).unwrap();
+        assert_eq!(diff.browsers_removed, vec!["Firefox"]);
+        assert_eq!(d.config().browsers.len(), 1);
+    }
+
+    #[test]
+    fn config_with_unknown_browser_is_not_applied() {
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_routing()))), true);
+        let mut broken = cfg_routing();
+        broken.patterns[0].browsers = vec!["Nope".into()];
+        let err = d.apply_config(broken).unwrap_err();
+        assert_eq!(reload_error_count(&err), 1);
+        assert_eq!(
+            d.config().patterns[0].browsers,
+            cfg_routing().patterns[0].browsers
+        );
+    }
+
+    #[test]
+    fn config_snapshot_survives_reload() {
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_empty()))), false);
+        let snapshot = d.config();
+        let mut updated = cfg_empty();
+        updated.version = 2;
+        // A reload swaps the shared config while the snapshot is still in use
+        *d.cfg.try_lock().unwrap() = Arc::new(updated);
+        assert_eq!(snapshot.version, 1);
+        assert_eq!(d.config().version, 2);
+    }
+
+    #[test]
+    fn activity_tracks_in_flight_calls() {
+        let activity = Arc::new(Activity::new());
+        let guard = activity.begin();
+        assert_eq!(activity.in_flight(), 1);
+        assert_eq!(activity.idle_for(), None);
+        drop(guard);
+        assert_eq!(activity.in_flight(), 0);
+        assert!(activity.idle_for().unwrap() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn drain_gives_up_after_grace_period() {
+        let activity = Arc::new(Activity::new());
+        let guard = activity.begin();
+        let started = Instant::now();
+        assert!(!async_io::block_on(drain(
+            &activity,
+            Duration::from_millis(200)
+        )));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        drop(guard);
+        assert!(async_io::block_on(drain(
+            &activity,
+            Duration::from_millis(200)
+        )));
+    }
+
+    #[test]
+    fn wait_idle_returns_after_timeout() {
+        let mut cfg = cfg_empty();
+        cfg.daemon.idle_exit_secs = 1;
+        let shared: SharedConfig = Arc::new(Mutex::new(Arc::new(cfg)));
+        let activity = Activity::new();
+        *activity.last.lock().unwrap() = Instant::now() - Duration::from_secs(2);
+        let start = Instant::now();
+        async_io::block_on(wait_idle(&shared, &activity));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    /// A daemon served over a peer-to-peer connection: `(server, client)`.
+    fn serve_p2p(d: MuxieDaemon) -> (zbus::Connection, zbus::Connection) {
+        let (a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+        async_io::block_on(async {
+            let server = zbus::connection::Builder::unix_stream(a)
+                .server(zbus::Guid::generate())
+                .unwrap()
+                .p2p()
+                .serve_at(DBUS_PATH, d)
+                .unwrap()
+                .build();
+            let client = zbus::connection::Builder::unix_stream(b).p2p().build();
+            let (server, client) = future::zip(server, client).await;
+            (server.unwrap(), client.unwrap())
+        })
+    }
+
+    /// Next signal on `stream` as `(member, message)`.
+    fn next_signal(stream: &mut zbus::MessageStream) -> (String, zbus::Message) {
+        let next = async {
+            loop {
+                let msg = stream.next().await.unwrap().unwrap();
+                if msg.message_type() == zbus::message::Type::Signal {
+                    let member = msg.header().member().unwrap().to_string();
+                    return (member, msg);
+                }
+            }
+        };
+        let timeout = async {
+            async_io::Timer::after(Duration::from_secs(5)).await;
+            panic!("no signal received");
+        };
+        async_io::block_on(future::or(next, timeout))
+    }
+
+    #[test]
+    fn notifier_emits_open_signals_with_redacted_urls() {
+        use crate::notify::{Notifier, NotifyPrefs};
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_routing()))), true);
+        let (server, client) = serve_p2p(d);
+        let mut stream = zbus::MessageStream::from(&client);
+        let notifier = SignalingNotifier {
+            emitter: Some(SignalEmitter::new(&server, DBUS_PATH).unwrap()),
+        };
+        let prefs = NotifyPrefs {
+            enabled: false,
+            redact_urls: false,
+        };
+
+        notifier.notify_opened("https://git.work.com/secret?x=1", Some("work.com"), "Work");
+        let (member, msg) = next_signal(&mut stream);
+        assert_eq!(member, "UrlOpened");
+        let body: (String, String, String) = msg.body().deserialize().unwrap();
+        assert_eq!(
+            body,
+            ("git.work.com".into(), "work.com".into(), "Work".into())
+        );
+
+        notifier.notify_opened("https://example.org/", None, "Firefox");
+        let (_, msg) = next_signal(&mut stream);
+        let body: (String, String, String) = msg.body().deserialize().unwrap();
+        assert_eq!(
+            body,
+            ("example.org".into(), String::new(), "Firefox".into())
+        );
+
+        notifier.notify_error(
+            "https://example.org/a",
+            "explicit browser",
+            "Firefox",
+            "not found",
+            &prefs,
+        );
+        let (member, msg) = next_signal(&mut stream);
+        assert_eq!(member, "OpenFailed");
+        let body: (String, String, String) = msg.body().deserialize().unwrap();
+        assert_eq!(
+            body,
+            ("example.org".into(), "Firefox".into(), "not found".into())
+        );
+
+        // Even with notifications showing full URLs, the signal carries none
+        notifier.notify_error(
+            "https://example.org/private",
+            "default",
+            "Firefox",
+            "Failed to open https://example.org/private",
+            &prefs,
+        );
+        let (_, msg) = next_signal(&mut stream);
+        let body: (String, String, String) = msg.body().deserialize().unwrap();
+        assert_eq!(body.2, "Failed to open example.org");
+    }
+
+    #[test]
+    fn reload_signals_and_updates_properties() {
+        let d = MuxieDaemon::new(Arc::new(Mutex::new(Arc::new(cfg_empty()))), true);
+        d.last_reload.store(0, Ordering::SeqCst);
+        let (server, client) = serve_p2p(d.clone());
+        let mut stream = zbus::MessageStream::from(&client);
+        let properties = async_io::block_on(async {
+            zbus::fdo::PropertiesProxy::builder(&client)
+                .destination(DBUS_SERVICE)
+                .unwrap()
+                .path(DBUS_PATH)
+                .unwrap()
+                .build()
+                .await
+                .unwrap()
+        });
+        let get = |name: &'static str| {
+            async_io::block_on(properties.get(
+                zbus_names::InterfaceName::from_static_str(DBUS_INTERFACE).unwrap(),
+                name,
+            ))
+            .unwrap()
+        };
+        assert_eq!(u32::try_from(get("PatternCount")).unwrap(), 0);
+
+        // A rejected file keeps the previous values
+        let mut broken = cfg_routing();
+        broken.patterns[0].browsers = vec!["Nope".into()];
+        let err = d.apply_config(broken).unwrap_err();
+        async_io::block_on(announce_reload(&server, false, reload_error_count(&err)));
+        let (member, msg) = next_signal(&mut stream);
+        assert_eq!(member, "ConfigReloaded");
+        assert_eq!(msg.body().deserialize::<(bool, u32)>().unwrap(), (false, 1));
+        assert_eq!(u32::try_from(get("PatternCount")).unwrap(), 0);
+        assert_eq!(u64::try_from(get("LastReloadTime")).unwrap(), 0);
+
+        let mut updated = cfg_routing();
+        updated.version = 2;
+        d.apply_config(updated).unwrap();
+        async_io::block_on(announce_reload(&server, true, 0));
+        let (member, msg) = next_signal(&mut stream);
+        assert_eq!(member, "ConfigReloaded");
+        assert_eq!(msg.body().deserialize::<(bool, u32)>().unwrap(), (true, 0));
+        let mut changed = Vec::new();
+        for _ in 0..3 {
+            let (member, msg) = next_signal(&mut stream);
+            assert_eq!(member, "PropertiesChanged");
+            let (_, values, _): (
+                String,
+                std::collections::HashMap<String, zbus::zvariant::OwnedValue>,
+                Vec<String>,
+            ) = msg.body().deserialize().unwrap();
+            changed.extend(values.into_keys());
+        }
+        changed.sort();
+        assert_eq!(changed, ["ConfigVersion", "LastReloadTime", "PatternCount"]);
+        assert_eq!(u32::try_from(get("ConfigVersion")).unwrap(), 2);
+        assert_eq!(u32::try_from(get("PatternCount")).unwrap(), 1);
+        assert!(u64::try_from(get("LastReloadTime")).unwrap() > 0);
+    }
 }
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Desktop context of the caller, used by `env` and `workspace` conditions.
+pub(crate) trait DesktopProbe {
+    fn env_var(&self, name: &str) -> Option<String>;
+    /// Name of the focused workspace (Sway, i3 or Hyprland).
+    fn active_workspace(&self) -> Option<String>;
+}
+
+/// Probe that reads the caller's environment and queries the compositor over IPC.
+pub(crate) struct SysDesktop {
+    /// Environment of the caller; `None` means this process's environment.
+    environ: Option<HashMap<String, String>>,
+}
+
+impl SysDesktop {
+    /// Use this process's environment.
+    pub(crate) fn current() -> Self {
+        SysDesktop { environ: None }
+    }
+
+    /// Use the environment of process `pid` (e.g. the D-Bus caller), falling back to
+    /// this process's environment if it cannot be read.
+    pub(crate) fn for_pid(pid: u32) -> Self {
+        let environ = std::fs::read(format!("/proc/{pid}/environ"))
+            .ok()
+            .map(|bytes| parse_environ(&bytes));
+        SysDesktop { environ }
+    }
+}
+
+impl DesktopProbe for SysDesktop {
+    fn env_var(&self, name: &str) -> Option<String> {
+        match &self.environ {
+            Some(env) => env.get(name).cloned(),
+            None => std::env::var(name).ok(),
+        }
+    }
+
+    fn active_workspace(&self) -> Option<String> {
+        if let Some(sock) = self.env_var("SWAYSOCK").or_else(|| self.env_var("I3SOCK")) {
+            return i3_focused_workspace(&sock);
+        }
+        if let Some(sig) = self.env_var("HYPRLAND_INSTANCE_SIGNATURE") {
+            return hyprland_active_workspace(self.env_var("XDG_RUNTIME_DIR"), &sig);
+        }
+        None
+    }
+}
+
+fn parse_environ(bytes: &[u8]) -> HashMap<String, String> {
+    bytes
+        .split(|b| *b == 0)
+        .filter_map(|kv| {
+            let kv = String::from_utf8_lossy(kv);
+            let (k, v) = kv.split_once('=')?;
+            Some((k.to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+const IPC_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Query Sway/i3 over the i3 IPC protocol (`GET_WORKSPACES`).
+fn i3_focused_workspace(sock: &str) -> Option<String> {
+    const GET_WORKSPACES: u32 = 1;
+    let mut stream = UnixStream::connect(sock).ok()?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(IPC_TIMEOUT)).ok()?;
+    let mut msg = b"i3-ipc".to_vec();
+    msg.extend_from_slice(&0u32.to_ne_bytes());
+    msg.extend_from_slice(&GET_WORKSPACES.to_ne_bytes());
+    stream.write_all(&msg).ok()?;
+
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header).ok()?;
+    if &header[..6] != b"i3-ipc" {
+        return None;
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().ok()?) as usize;
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).ok()?;
+    focused_from_i3_workspaces(&payload)
+}
+
+fn focused_from_i3_workspaces(payload: &[u8]) -> Option<String> {
+    let workspaces: Vec<serde_json::Value> = serde_json::from_slice(payload).ok()?;
+    workspaces
+        .iter()
+        .find(|ws| ws.get("focused").and_then(|f| f.as_bool()) == Some(true))
+        .and_then(|ws| ws.get("name")?.as_str().map(String::from))
+}
+
+/// Query Hyprland's request socket (`j/activeworkspace`).
+fn hyprland_active_workspace(runtime_dir: Option<String>, signature: &str) -> Option<String> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = runtime_dir {
+        candidates.push(
+            PathBuf::from(dir)
+                .join("hypr")
+                .join(signature)
+                .join(".socket.sock"),
+        );
+    }
+    // Older Hyprland releases used /tmp
+    candidates.push(
+        PathBuf::from("/tmp/hypr")
+            .join(signature)
+            .join(".socket.sock"),
+    );
+    let mut stream = candidates
+        .iter()
+        .find_map(|p| UnixStream::connect(p).ok())?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(IPC_TIMEOUT)).ok()?;
+    stream.write_all(b"j/activeworkspace").ok()?;
+    let mut payload = Vec::new();
+    stream.read_to_end(&mut payload).ok()?;
+    name_from_hyprland_workspace(&payload)
+}
+
+fn name_from_hyprland_workspace(payload: &[u8]) -> Option<String> {
+    let ws: serde_json::Value = serde_json::from_slice(payload).ok()?;
+    ws.get("name")?.as_str().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environ_parsing() {
+        let env = parse_environ(b"XDG_CURRENT_DESKTOP=sway\0FOO=a=b\0BROKEN\0");
+        assert_eq!(
+            env.get("XDG_CURRENT_DESKTOP").map(|s| s.as_str()),
+            Some("sway")
+        );
+        assert_eq!(env.get("FOO").map(|s| s.as_str()), Some("a=b"));
+        assert!(!env.contains_key("BROKEN"));
+    }
+
+    #[test]
+    fn i3_focused_workspace_parsing() {
+        let payload = br#"[{"num":1,"name":"1: web","focused":false},{"num":2,"name":"client-A","focused":true}]"#;
+        assert_eq!(
+            focused_from_i3_workspaces(payload).as_deref(),
+            Some("client-A")
+        );
+        assert_eq!(focused_from_i3_workspaces(b"[]"), None);
+    }
+
+    #[test]
+    fn hyprland_workspace_parsing() {
+        let payload = br#"{"id":3,"name":"client-B","monitor":"DP-1","windows":2}"#;
+        assert_eq!(
+            name_from_hyprland_workspace(payload).as_deref(),
+            Some("client-B")
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use crate::cli::ValidateFormat;
+use crate::config::{Severity, ValidationError, ValidationResult, read_config};
+use crate::sources::ParseError;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Position in the configuration file; line and column are 1-based, the column counts
+/// characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    fn at(text: &str, offset: usize) -> Self {
+        let before = &text[..offset.min(text.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// Split a validation path such as `patterns[3].browsers[1]` into keys and indices.
+fn segments(path: &str) -> Vec<Segment<'_>> {
+    let mut out = Vec::new();
+    for part in path.split('.') {
+        let (key, mut rest) = part.split_once('[').map_or((part, ""), |(k, r)| (k, r));
+        if !key.is_empty() {
+            out.push(Segment::Key(key));
+        }
+        while let Some((index, tail)) = rest.split_once(']') {
+            if let Ok(i) = index.parse() {
+                out.push(Segment::Index(i));
+            }
+            rest = tail.strip_prefix('[').unwrap_or(tail);
+        }
+    }
+    out
+}
+
+/// Location of the value at `path` in `text`. When part of the path is missing from the
+/// file (e.g. an omitted field), the closest enclosing value is used.
+pub(crate) fn locate(text: &str, path: &str) -> Option<Location> {
+    let doc = toml_edit::ImDocument::parse(text).ok()?;
+    let mut item = doc.as_item();
+    let mut span = None;
+    for segment in segments(path) {
+        let next = match segment {
+            Segment::Key(key) => item.get(key),
+            Segment::Index(i) => item.get(i),
+        };
+        let Some(next) = next else { break };
+        span = next.span().or(span);
+        item = next;
+    }
+    span.map(|s| Location::at(text, s.start))
+}
+
+/// A validation issue and where it is in the configuration file.
+#[derive(Debug, Serialize)]
+struct Located<'a> {
+    #[serde(flatten)]
+    issue: &'a ValidationError,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+}
+
+/// Locate each issue in `main_text`, or in the included file it names.
+fn locate_all<'a>(main_text: &str, issues: &'a [ValidationError]) -> Vec<Located<'a>> {
+    let mut included: HashMap<&str, String> = HashMap::new();
+    issues
+        .iter()
+        .map(|issue| {
+            let text = match issue.file.as_deref() {
+                None => main_text,
+                Some(file) => included
+                    .entry(file)
+                    .or_insert_with(|| std::fs::read_to_string(file).unwrap_or_default()),
+            };
+            Located {
+                issue,
+                location: issue.path.as_deref().and_then(|p| locate(text, p)),
+            }
+        })
+        .collect()
+}
+
+fn json_report(file: &Path, issues: &[Located]) -> serde_json::Value {
+    let count = |severity| {
+        issues
+            .iter()
+            .filter(|l| l.issue.severity == severity)
+            .count()
+    };
+    json!({
+        "file": file.display().to_string(),
+        "errors": count(Severity::Error),
+        "warnings": count(Severity::Warning),
+        "issues": issues,
+    })
+}
+
+/// SARIF 2.1.0 log with one result per issue, for code scanning tools and editors.
+fn sarif_report(file: &Path, issues: &[Located]) -> serde_json::Value {
+    let mut rules: Vec<&str> = issues.iter().map(|l| l.issue.code.as_str()).collect();
+    rules.sort_unstable();
+    rules.dedup();
+    let results: Vec<serde_json::Value> = issues
+        .iter()
+        .map(|l| {
+            let mut location = json!({
+                "physicalLocation": {
+                    "artifactLocation": {
+                        "uri": file_uri(l.issue.file.as_deref().map_or(file, Path::new)),
+                    },
+                },
+            });
+            if let Some(loc) = l.location {
+                location["physicalLocation"]["region"] =
+                    json!({ "startLine": loc.line, "startColumn": loc.column });
+            }
+            if let Some(path) = &l.issue.path {
+                location["logicalLocations"] = json!([{ "fullyQualifiedName": path }]);
+            }
+            json!({
+                "ruleId": l.issue.code,
+                "ruleIndex": rules.binary_search(&l.issue.code.as_str()).unwrap_or_default(),
+                "level": match l.issue.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                },
+                "message": { "text": l.issue.message },
+                "locations": [location],
+            })
+        })
+        .collect();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "muxie",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                    "rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    uri
+}
+
+/// An unreadable or unparsable configuration file as a single `config.read` error,
+/// located at the TOML syntax error if there is one.
+fn read_failure(err: &anyhow::Error, main: &Path) -> (ValidationError, Option<Location>) {
+    let parse = err.downcast_ref::<ParseError>();
+    let file = parse.map_or(main, |p| p.file.as_path());
+    let location = parse.and_then(|p| {
+        let text = std::fs::read_to_string(file).ok()?;
+        let span = p.error.span()?;
+        // Errors at the end of the file point past the final newline
+        let offset = if span.start >= text.len() {
+            text.trim_end_matches('\n').len()
+        } else {
+            span.start
+        };
+        Some(Location::at(&text, offset))
+    });
+    let issue = ValidationError {
+        severity: Severity::Error,
+        code: "config.read".to_string(),
+        message: format!("{err:#}"),
+        path: None,
+        file: (file != main).then(|| file.display().to_string()),
+    };
+    (issue, location)
+}
+
+fn print_machine(format: ValidateFormat, file: &Path, issues: &[Located]) {
+    let report = match format {
+        ValidateFormat::Sarif => sarif_report(file, issues),
+        _ => json_report(file, issues),
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).expect("reports serialize")
+    );
+}
+
+/// `muxie config validate`. Returns the exit status: 0 when valid (or only warnings
+/// without `--deny-warnings`), 1 on validation failures and 2 when the file cannot be read.
+pub(crate) fn validate(format: ValidateFormat, deny_warnings: bool) -> i32 {
+    let file = crate::paths::config_path();
+    let cfg = match read_config() {
+        Ok(cfg) => cfg,
+        Err(err) if format == ValidateFormat::Human => {
+            eprintln!("Error validating configuration: {err}");
+            return 2;
+        }
+        Err(err) => {
+            let (issue, location) = read_failure(&err, &file);
+            print_machine(
+                format,
+                &file,
+                &[Located {
+                    issue: &issue,
+                    location,
+                }],
+            );
+            return 2;
+        }
+    };
+    let result: ValidationResult = cfg.validate(true);
+    let failed = result.has_errors() || (deny_warnings && !result.is_empty());
+    match format {
+        ValidateFormat::Human if result.is_empty() => println!("Configuration is valid"),
+        ValidateFormat::Human => result.print(),
+        _ => {
+            let text = std::fs::read_to_string(&file).unwrap_or_default();
+            print_machine(format, &file, &locate_all(&text, &result.issues));
+        }
+    }
+    i32::from(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    const TEXT: &str = r#"version = 1
+
+[[browsers]]
+name = "Firefox"
+executable = "firefox"
+
+[[patterns]]
+pattern = "example.com"
+browsers = ["Firefox", "Nope"]
+
+[logging]
+targets = { routing = "debug", bogus = "trace" }
+"#;
+
+    #[test]
+    fn parses_paths() {
+        assert_eq!(
+            segments("patterns[3].browsers[1]"),
+            vec![
+                Segment::Key("patterns"),
+                Segment::Index(3),
+                Segment::Key("browsers"),
+                Segment::Index(1)
+            ]
+        );
+        assert_eq!(
+            segments("logging.targets.x"),
+            vec![
+                Segment::Key("logging"),
+                Segment::Key("targets"),
+                Segment::Key("x")
+            ]
+        );
+    }
+
+    #[test]
+    fn locates_paths_in_toml() {
+        let at = |line, column| Some(Location { line, column });
+        assert_eq!(locate(TEXT, "patterns[0].browsers[1]"), at(9, 24));
+        assert_eq!(locate(TEXT, "browsers[0].executable"), at(5, 14));
+        assert_eq!(locate(TEXT, "logging.targets.bogus"), at(12, 40));
+        // A missing field points at the closest value that exists
+        assert_eq!(
+            locate(TEXT, "patterns[0].fallback[0]"),
+            locate(TEXT, "patterns[0]")
+        );
+        assert!(locate(TEXT, "patterns[0]").is_some());
+        assert_eq!(locate(TEXT, "dialog.provider"), None);
+    }
+
+    #[test]
+    fn sarif_and_json_reports() {
+        let issues = vec![
+            ValidationError {
+                severity: Severity::Error,
+                code: "pattern.browser.unknown".into(),
+                message: "Unknown browser in pattern: 'Nope'".into(),
+                path: Some("patterns[0].browsers[1]".into()),
+                file: None,
+            },
+            ValidationError {
+                severity: Severity::Warning,
+                code: "pattern.shadowed".into(),
+                message: "shadowed".into(),
+                path: None,
+                file: None,
+            },
+        ];
+        let located = locate_all(TEXT, &issues);
+        let file = Path::new("/home/u/my config/muxie.toml");
+
+        let report = json_report(file, &located);
+        assert_eq!(report["errors"], 1);
+        assert_eq!(report["warnings"], 1);
+        assert_eq!(report["issues"][0]["severity"], "error");
+        assert_eq!(report["issues"][0]["line"], 9);
+        assert_eq!(report["issues"][0]["column"], 24);
+        assert!(report["issues"][1].get("line").is_none());
+
+        let sarif = sarif_report(file, &located);
+        let results = &sarif["runs"][0]["results"];
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[0]["ruleIndex"], 0);
+        assert_eq!(results[1]["ruleIndex"], 1);
+        let physical = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(
+            physical["artifactLocation"]["uri"],
+            "file:///home/u/my%20config/muxie.toml"
+        );
+        assert_eq!(physical["region"]["startLine"], 9);
+        assert!(results[1]["locations"][0]["physicalLocation"]["region"].is_null());
+    }
+
+    #[test]
+    fn parse_errors_are_located() {
+        let dir = TempDir::new("diag");
+        let main = dir.join("muxie.toml");
+        let included = dir.join("work.toml");
+        let failure = |file: &Path, text: &str| {
+            std::fs::write(file, text).unwrap();
+            let err = anyhow::Error::new(ParseError {
+                file: file.to_path_buf(),
+                error: toml::from_str::<toml::Value>(text).unwrap_err(),
+            });
+            read_failure(&err, &main)
+        };
+
+        let (issue, location) = failure(&main, "version = 1\n[[browsers]\n");
+        assert_eq!(issue.code, "config.read");
+        assert_eq!(issue.file, None);
+        assert_eq!(location.map(|l| l.line), Some(2));
+        let (issue, location) = failure(&included, "version = 1\nx = [\n");
+        assert_eq!(issue.file.as_deref(), included.to_str());
+        assert_eq!(location, Some(Location { line: 2, column: 6 }));
+    }
+
+    #[test]
+    fn main_file_issues_are_located_after_locked_system_patterns() {
+        let dir = TempDir::new("diag-layered");
+        let main = dir.join("user.toml");
+        let system = dir.join("system.toml");
+        std::fs::write(
+            &system,
+            "[[browsers]]\nname = \"Managed\"\nexecutable = \"sh\"\n\n[[patterns]]\npattern = \"sso\"\nbrowsers = [\"Managed\"]\nlocked = true\n\n[[patterns]]\npattern = \"intranet\"\nbrowsers = [\"Managed\"]\nlocked = true\n",
+        )
+        .unwrap();
+        let text = "[[browsers]]\nname = \"Mine\"\nexecutable = \"sh\"\n\n[[patterns]]\npattern = \"work\"\nbrowsers = [\n  \"Mine\",\n  \"Nope\",\n]\n";
+
+        let config = crate::sources::load_layered_text(&main, text, &[system]).unwrap();
+        let issues: Vec<ValidationError> = config
+            .validate(true)
+            .issues
+            .into_iter()
+            .filter(|e| e.code == "pattern.browser.unknown")
+            .collect();
+        let located = locate_all(text, &issues);
+        assert_eq!(located.len(), 1);
+        assert_eq!(located[0].issue.file, None);
+        assert_eq!(
+            located[0].issue.path.as_deref(),
+            Some("patterns[0].browsers[1]")
+        );
+        assert_eq!(located[0].location, Some(Location { line: 9, column: 3 }));
+    }
+}
@@ -0,0 +1,403 @@
+use crate::config::{Config, ValidationError, ValidationResult, installed_browsers, read_config};
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, Item, Table, value};
+
+/// Array of `[[key]]` tables in `doc`, created if missing.
+fn tables<'a>(doc: &'a mut DocumentMut, key: &str) -> Result<&'a mut ArrayOfTables> {
+    let item = doc
+        .entry(key)
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()));
+    match item.as_array_of_tables_mut() {
+        Some(tables) => Ok(tables),
+        None => bail!("`{key}` is not written as [[{key}]] tables; edit the file by hand"),
+    }
+}
+
+fn string_array<I: IntoIterator<Item = S>, S: Into<String>>(items: I) -> Array {
+    let mut array = Array::new();
+    for item in items {
+        array.push(item.into());
+    }
+    array
+}
+
+/// Index of a rule in the main file, or an error naming the valid range.
+fn check_index(index: usize, len: usize) -> Result<()> {
+    if index >= len {
+        bail!("There is no rule {index}; the configuration file has {len} rule(s)");
+    }
+    Ok(())
+}
+
+/// Put `tables` into `aot` in the given order. Existing tables take over the document
+/// positions of the old entries in turn, so the file lists them in the new order; new
+/// tables follow the table before them.
+fn reorder(aot: &mut ArrayOfTables, mut tables: Vec<Table>) {
+    let mut positions: Vec<usize> = aot.iter().filter_map(Table::position).collect();
+    positions.sort_unstable();
+    let mut positions = positions.into_iter();
+    for table in tables.iter_mut().filter(|t| t.position().is_some()) {
+        if let Some(position) = positions.next() {
+            table.set_position(position);
+        }
+    }
+    aot.clear();
+    for table in tables {
+        aot.push(table);
+    }
+}
+
+/// Errors and warnings in `after` that `before` does not have. Each issue in `before`
+/// accounts for one issue in `after`, so new copies of an existing error still count.
+/// Issues are paired by location first; what is left pairs up regardless of path, since
+/// inserting or moving rules renumbers the issues of the rules after them.
+fn introduced(before: &ValidationResult, after: ValidationResult) -> Vec<ValidationError> {
+    let same = |a: &ValidationError, b: &ValidationError| {
+        a.severity == b.severity && a.code == b.code && a.message == b.message && a.file == b.file
+    };
+    let mut known: Vec<Option<&ValidationError>> = before.issues.iter().map(Some).collect();
+    let mut take = |e: &ValidationError, at_path: bool| {
+        let found = known
+            .iter_mut()
+            .find(|k| k.is_some_and(|k| same(k, e) && (!at_path || k.path == e.path)));
+        found.map(Option::take).is_some()
+    };
+    let (_, unmatched): (Vec<_>, Vec<_>) = after.issues.into_iter().partition(|e| take(e, true));
+    unmatched.into_iter().filter(|e| !take(e, false)).collect()
+}
+
+/// Apply `edit` to the configuration file, keeping its comments and formatting, and write
+/// it back unless the result has validation errors the file did not have before.
+/// `edit` returns the message printed on success.
+fn edit_config<F>(edit: F) -> Result<()>
+where
+    F: FnOnce(&mut DocumentMut, &Config) -> Result<String>,
+{
+    let path = crate::paths::config_path();
+    if !path.is_file() {
+        bail!(
+            "Configuration file not found: {} (create it with `muxie config create`)",
+            path.display()
+        );
+    }
+    let before = read_config()?;
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let mut doc: DocumentMut = text
+        .parse()
+        .with_context(|| format!("Failed to parse config file (TOML): {}", path.display()))?;
+    let done = edit(&mut doc, &before)?;
+    let edited = doc.to_string();
+    write_checked(
+        &path,
+        &edited,
+        &before,
+        &crate::paths::system_config_paths(),
+    )?;
+    println!("{done}");
+    Ok(())
+}
+
+/// Write `edited` to `path` unless it has validation errors `before` did not have, with
+/// `system` layered under it as when it is loaded.
+fn write_checked(path: &Path, edited: &str, before: &Config, system: &[PathBuf]) -> Result<()> {
+    let after = crate::sources::load_layered_text(path, edited, system)?;
+    let issues = introduced(&before.validate(true), after.validate(true));
+    let result = ValidationResult { issues };
+    if result.has_errors() {
+        result.print();
+        bail!("Configuration not changed");
+    }
+    if !result.is_empty() {
+        result.print();
+    }
+    std::fs::write(path, edited)
+        .with_context(|| format!("Failed to write config file: {}", path.display()))
+}
+
+/// `muxie browser add`. Without `executable`, the browser is taken from the installed
+/// browsers by name (case-insensitively).
+pub(crate) fn add_browser(name: &str, executable: Option<&str>, args: &[String]) -> Result<()> {
+    let browser = match executable {
+        Some(executable) => crate::browser::Browser {
+            name: name.to_string(),
+            executable: executable.to_string(),
+            args: args.to_vec(),
+            locked: false,
+        },
+        None => {
+            let installed = installed_browsers();
+            match installed.iter().find(|b| b.name.eq_ignore_ascii_case(name)) {
+                Some(b) => b.clone(),
+                None if installed.is_empty() => {
+                    bail!("No installed browsers found; pass --executable for '{name}'")
+                }
+                None => {
+                    let names: Vec<&str> = installed.iter().map(|b| b.name.as_str()).collect();
+                    bail!(
+                        "No installed browser named '{name}'; pass --executable or use one of: {}",
+                        names.join(", ")
+                    );
+                }
+            }
+        }
+    };
+    edit_config(|doc, _| {
+        let mut table = Table::new();
+        table["name"] = value(&browser.name);
+        table["executable"] = value(&browser.executable);
+        if !browser.args.is_empty() {
+            table["args"] = value(string_array(&browser.args));
+        }
+        tables(doc, "browsers")?.push(table);
+        Ok(format!("Added browser '{}'", browser.name))
+    })
+}
+
+/// `muxie browser remove`. Refused while rules still use the browser.
+pub(crate) fn remove_browser(name: &str) -> Result<()> {
+    edit_config(|doc, config| {
+        let browsers = tables(doc, "browsers")?;
+        let index = browsers
+            .iter()
+            .position(|t| t.get("name").and_then(Item::as_str) == Some(name));
+        match index {
+            Some(i) => browsers.remove(i),
+            None => match config.browsers.iter().position(|b| b.name == name) {
+                Some(i) => bail!(
+                    "Browser '{name}' is defined in {}; edit that file instead",
+                    config.provenance.browsers[i]
+                        .file
+                        .as_deref()
+                        .unwrap_or(Path::new("another file"))
+                        .display()
+                ),
+                None => bail!("No browser named '{name}'"),
+            },
+        }
+        Ok(format!("Removed browser '{name}'"))
+    })
+}
+
+/// `muxie browser list`: configured browsers, or with `installed` the browsers found
+/// on the system.
+pub(crate) fn list_browsers(installed: bool) -> Result<()> {
+    if installed {
+        let configured: Vec<String> = read_config()
+            .map(|c| c.browsers.into_iter().map(|b| b.name).collect())
+            .unwrap_or_default();
+        for b in installed_browsers() {
+            let mark = if configured.contains(&b.name) {
+                "  (configured)"
+            } else {
+                ""
+            };
+            println!("{}  {}{mark}", b.name, b.command_line("%u").join(" "));
+        }
+        return Ok(());
+    }
+    let config = read_config()?;
+    for (b, origin) in config.browsers.iter().zip(&config.provenance.browsers) {
+        let file = match &origin.file {
+            Some(file) => format!("  ({})", file.display()),
+            None => String::new(),
+        };
+        println!("{}  {}{file}", b.name, b.command_line("%u").join(" "));
+    }
+    Ok(())
+}
+
+/// `muxie rule add`: a rule sending URLs that match `pattern` to `browsers`, appended or
+/// inserted before rule `at`.
+pub(crate) fn add_rule(
+    pattern: &str,
+    browsers: &[String],
+    at: Option<usize>,
+    prefer_running: bool,
+) -> Result<()> {
+    edit_config(|doc, _| {
+        let rules = tables(doc, "patterns")?;
+        let at = at.unwrap_or(rules.len());
+        if at > rules.len() {
+            bail!(
+                "Cannot insert at {at}; the configuration file has {} rule(s)",
+                rules.len()
+            );
+        }
+        let mut table = Table::new();
+        table["pattern"] = value(pattern);
+        table["browsers"] = value(string_array(browsers));
+        if prefer_running {
+            table["prefer_running"] = value(true);
+        }
+        let mut all: Vec<Table> = rules.iter().cloned().collect();
+        all.insert(at, table);
+        reorder(rules, all);
+        Ok(format!(
+            "Added rule {at}: '{pattern}' → {}",
+            browsers.join(", ")
+        ))
+    })
+}
+
+/// `muxie rule remove`.
+pub(crate) fn remove_rule(index: usize) -> Result<()> {
+    edit_config(|doc, _| {
+        let rules = tables(doc, "patterns")?;
+        check_index(index, rules.len())?;
+        let pattern = rules
+            .get(index)
+            .and_then(|t| t.get("pattern"))
+            .and_then(Item::as_str)
+            .unwrap_or_default()
+            .to_string();
+        rules.remove(index);
+        Ok(format!("Removed rule {index}: '{pattern}'"))
+    })
+}
+
+/// `muxie rule move`: move rule `from` so that it becomes rule `to`.
+pub(crate) fn move_rule(from: usize, to: usize) -> Result<()> {
+    edit_config(|doc, _| {
+        let rules = tables(doc, "patterns")?;
+        check_index(from, rules.len())?;
+        check_index(to, rules.len())?;
+        let mut all: Vec<Table> = rules.iter().cloned().collect();
+        let table = all.remove(from);
+        let pattern = table
+            .get("pattern")
+            .and_then(Item::as_str)
+            .unwrap_or_default()
+            .to_string();
+        all.insert(to, table);
+        reorder(rules, all);
+        Ok(format!("Moved rule '{pattern}' from {from} to {to}"))
+    })
+}
+
+/// `muxie rule list`: rules in routing order. Rules of the configuration file show the
+/// index used by `rule remove` and `rule move`; others name their file.
+pub(crate) fn list_rules() -> Result<()> {
+    let config = read_config()?;
+    for (p, origin) in config.patterns.iter().zip(&config.provenance.patterns) {
+        let line = format!("'{}' → {}", p.pattern, p.browsers.join(", "));
+        match &origin.file {
+            None => println!("{:>3}  {line}", origin.index),
+            Some(file) => println!("     {line}  ({})", file.display()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    const TEXT: &str = r#"version = 1
+
+# Personal browser
+[[browsers]]
+name = "Firefox"
+executable = "firefox"
+
+# Work first
+[[patterns]]
+pattern = "work.com"
+browsers = ["Firefox"]
+
+# Docs
+[[patterns]]
+pattern = "docs.rs"
+browsers = ["Firefox"] # trailing
+
+[[patterns]]
+pattern = "news"
+browsers = ["Firefox"]
+
+[dialog]
+provider = "auto"
+"#;
+
+    fn order(doc: &DocumentMut) -> Vec<String> {
+        let config: Config = toml::from_str(&doc.to_string()).unwrap();
+        config.patterns.into_iter().map(|p| p.pattern).collect()
+    }
+
+    #[test]
+    fn reorders_rules_and_keeps_comments() {
+        let mut doc: DocumentMut = TEXT.parse().unwrap();
+        let rules = tables(&mut doc, "patterns").unwrap();
+        let mut all: Vec<Table> = rules.iter().cloned().collect();
+        let moved = all.remove(2);
+        all.insert(0, moved);
+        reorder(rules, all);
+        assert_eq!(order(&doc), ["news", "work.com", "docs.rs"]);
+        let text = doc.to_string();
+        assert!(text.contains(
+            "# Docs\n[[patterns]]\npattern = \"docs.rs\"\nbrowsers = [\"Firefox\"] # trailing\n"
+        ));
+        assert!(text.ends_with("[dialog]\nprovider = \"auto\"\n"));
+
+        let rules = tables(&mut doc, "patterns").unwrap();
+        let mut all: Vec<Table> = rules.iter().cloned().collect();
+        let mut table = Table::new();
+        table["pattern"] = value("new");
+        table["browsers"] = value(string_array(["Firefox"]));
+        all.insert(1, table);
+        reorder(rules, all);
+        assert_eq!(order(&doc), ["news", "new", "work.com", "docs.rs"]);
+        let text = doc.to_string();
+        assert!(text.contains("[[patterns]]\npattern = \"new\"\nbrowsers = [\"Firefox\"]\n"));
+        assert!(text.starts_with("version = 1\n\n# Personal browser\n[[browsers]]"));
+        assert!(text.ends_with("[dialog]\nprovider = \"auto\"\n"));
+    }
+
+    #[test]
+    fn counts_new_copies_of_existing_issues() {
+        let unknown = |path: &str| ValidationError {
+            severity: crate::config::Severity::Error,
+            code: "pattern.browser.unknown".into(),
+            message: "Unknown browser in pattern: 'X'".into(),
+            path: Some(path.into()),
+            file: None,
+        };
+        let result = |paths: &[&str]| ValidationResult {
+            issues: paths.iter().map(|p| unknown(p)).collect(),
+        };
+        let before = result(&["patterns[0].browsers[0]"]);
+
+        // Another rule naming the same unknown browser is a new error
+        let new = introduced(
+            &before,
+            result(&["patterns[0].browsers[0]", "patterns[1].browsers[0]"]),
+        );
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].path.as_deref(), Some("patterns[1].browsers[0]"));
+        // A rule inserted in front only renumbers the existing one
+        assert!(introduced(&before, result(&["patterns[1].browsers[0]"])).is_empty());
+        assert!(introduced(&before, result(&[])).is_empty());
+    }
+
+    #[test]
+    fn refuses_edits_that_add_errors() {
+        let dir = TempDir::new("edit");
+        let path = dir.join("muxie.toml");
+        let text = TEXT.replace("firefox", "sh");
+        std::fs::write(&path, &text).unwrap();
+        let before = crate::sources::load(&path).unwrap();
+
+        // Removing a browser that rules still use
+        let mut doc: DocumentMut = text.parse().unwrap();
+        tables(&mut doc, "browsers").unwrap().remove(0);
+        assert!(write_checked(&path, &doc.to_string(), &before, &[]).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+
+        let mut doc: DocumentMut = text.parse().unwrap();
+        tables(&mut doc, "patterns").unwrap().remove(1);
+        write_checked(&path, &doc.to_string(), &before, &[]).unwrap();
+        let after = crate::sources::load(&path).unwrap();
+        assert_eq!(after.patterns.len(), 2);
+    }
+}
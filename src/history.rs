@@ -0,0 +1,355 @@
+use crate::config::HistoryOptions;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Outcome {
+    Opened,
+    Failed,
+    Canceled,
+}
+
+/// One line of the history file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    /// Unix time in seconds.
+    pub time: i64,
+    /// Host only, unless `history.redact_urls` is off.
+    pub url: String,
+    /// Pattern that selected the browser; `None` for the default browser.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Browser that opened the URL, or the last one tried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub browser: Option<String>,
+    /// Whether the selection dialog was shown.
+    pub dialog: bool,
+    /// Whether the pattern's `fallback` browsers were used because a condition did not hold.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fallback: bool,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What `open_url_with` did for a URL, collected while routing.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Trace {
+    pub pattern: Option<String>,
+    pub browser: Option<String>,
+    pub dialog: bool,
+    pub fallback: bool,
+}
+
+impl Entry {
+    pub(crate) fn new(url: &str, opts: &HistoryOptions, trace: Trace, result: &Result<()>) -> Self {
+        let (outcome, error) = match result {
+            Ok(()) => (Outcome::Opened, None),
+            Err(e) if e.downcast_ref::<crate::open::Canceled>().is_some() => {
+                (Outcome::Canceled, None)
+            }
+            Err(e) => (Outcome::Failed, Some(format!("{e:#}"))),
+        };
+        Entry {
+            time: chrono::Local::now().timestamp(),
+            url: crate::notify::shown_url(url, opts.redact_urls),
+            pattern: trace.pattern,
+            browser: trace.browser,
+            dialog: trace.dialog,
+            fallback: trace.fallback,
+            outcome,
+            error,
+        }
+    }
+}
+
+/// Append `entry` to the history file, creating it readable only by the user.
+pub(crate) fn append(path: &Path, entry: &Entry) -> Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to open history file {}", path.display()))?;
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    // A single write keeps concurrent appends from interleaving
+    file.write_all(&line)?;
+    Ok(())
+}
+
+/// Read all entries, oldest first. Lines that cannot be parsed are skipped.
+pub(crate) fn read(path: &Path) -> Result<Vec<Entry>> {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    // Split on raw bytes so a torn or non-UTF-8 line only loses itself
+    Ok(std::io::BufReader::new(file)
+        .split(b'\n')
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_slice(&line).ok())
+        .collect())
+}
+
+/// Filters for `muxie history`.
+#[derive(Debug, Default)]
+pub(crate) struct Query {
+    /// Only entries at or after this Unix time.
+    pub since: Option<i64>,
+    /// Only entries for this browser (case-insensitive).
+    pub browser: Option<String>,
+}
+
+impl Query {
+    fn matches(&self, entry: &Entry) -> bool {
+        self.since.is_none_or(|since| entry.time >= since)
+            && self.browser.as_ref().is_none_or(|name| {
+                entry
+                    .browser
+                    .as_ref()
+                    .is_some_and(|b| b.eq_ignore_ascii_case(name))
+            })
+    }
+}
+
+/// Parse `--since`: a duration back from `now` (`30m`, `12h`, `7d`, `2w`) or a local
+/// date or date and time (`2025-09-01`, `2025-09-01 14:00`).
+pub(crate) fn parse_since(spec: &str, now: chrono::DateTime<chrono::Local>) -> Result<i64> {
+    use chrono::{NaiveDate, NaiveDateTime, TimeZone};
+    let spec = spec.trim();
+    if let Some(unit) = spec.chars().last()
+        && let Ok(n) = spec[..spec.len() - unit.len_utf8()].parse::<i64>()
+    {
+        let secs = match unit {
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 7 * 86400,
+            _ => bail!("Unknown unit '{unit}' in --since (use m, h, d or w)"),
+        };
+        if n <= 0 {
+            bail!("--since duration must be positive, not '{spec}'");
+        }
+        return n
+            .checked_mul(secs)
+            .and_then(|back| now.timestamp().checked_sub(back))
+            .with_context(|| format!("--since '{spec}' is too far back"));
+    }
+    let naive = NaiveDateTime::parse_from_str(spec, "%Y-%m-%d %H:%M")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(spec, "%Y-%m-%d")
+                .map(|d| d.and_hms_opt(0, 0, 0).expect("midnight is valid"))
+        })
+        .with_context(|| format!("Invalid --since '{spec}' (e.g. 12h, 7d, 2025-09-01)"))?;
+    match chrono::Local.from_local_datetime(&naive).earliest() {
+        Some(t) => Ok(t.timestamp()),
+        None => bail!("'{spec}' does not exist in the local timezone"),
+    }
+}
+
+fn print_to<W: Write>(mut w: W, entries: &[&Entry]) {
+    use chrono::TimeZone;
+    for e in entries {
+        let time = chrono::Local
+            .timestamp_opt(e.time, 0)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| e.time.to_string());
+        let outcome = match e.outcome {
+            Outcome::Opened => "opened",
+            Outcome::Failed => "failed",
+            Outcome::Canceled => "canceled",
+        };
+        let mut details: Vec<String> = Vec::new();
+        if let Some(pattern) = &e.pattern {
+            details.push(format!("pattern '{pattern}'"));
+        }
+        if e.fallback {
+            details.push("fallback".to_string());
+        }
+        if e.dialog {
+            details.push("dialog".to_string());
+        }
+        if let Some(error) = &e.error {
+            details.push(error.clone());
+        }
+        let details = if details.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", details.join(", "))
+        };
+        let _ = writeln!(
+            w,
+            "{time}  {outcome:<8}  {:<12}  {}{details}",
+            e.browser.as_deref().unwrap_or("-"),
+            e.url
+        );
+    }
+}
+
+/// `muxie history`: list recorded opens.
+pub(crate) fn show(since: Option<&str>, browser: Option<&str>, json: bool) -> Result<()> {
+    let query = Query {
+        since: since
+            .map(|s| parse_since(s, chrono::Local::now()))
+            .transpose()?,
+        browser: browser.map(str::to_string),
+    };
+    let path = crate::paths::history_path();
+    let entries = read(&path)?;
+    if entries.is_empty() && !json {
+        eprintln!("No history recorded. Enable it with `enabled = true` in the [history] section.");
+        return Ok(());
+    }
+    let selected: Vec<&Entry> = entries.iter().filter(|e| query.matches(e)).collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&selected)?);
+    } else {
+        print_to(std::io::stdout(), &selected);
+    }
+    Ok(())
+}
+
+/// `muxie history clear`: delete the history file.
+pub(crate) fn clear() -> Result<()> {
+    let path = crate::paths::history_path();
+    match std::fs::remove_file(&path) {
+        Ok(()) => println!("Cleared history"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => println!("History is empty"),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to remove {}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    fn entry(time: i64, browser: &str, outcome: Outcome) -> Entry {
+        Entry {
+            time,
+            url: "example.com".into(),
+            pattern: Some("example".into()),
+            browser: Some(browser.into()),
+            dialog: false,
+            fallback: false,
+            outcome,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn appends_reads_and_filters() {
+        let dir = TempDir::new("history");
+        let path = dir.join("history.jsonl");
+        append(&path, &entry(100, "Firefox", Outcome::Opened)).unwrap();
+        append(&path, &entry(200, "Work", Outcome::Failed)).unwrap();
+        // A partially written line is skipped
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"time\":")
+            .unwrap();
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        let query = Query {
+            since: Some(150),
+            ..Default::default()
+        };
+        assert!(!query.matches(&entries[0]) && query.matches(&entries[1]));
+        let query = Query {
+            browser: Some("firefox".into()),
+            ..Default::default()
+        };
+        assert!(query.matches(&entries[0]) && !query.matches(&entries[1]));
+
+        let mut out = Vec::new();
+        print_to(&mut out, &[&entries[1]]);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("  failed    Work          example.com (pattern 'example')\n"));
+    }
+
+    #[test]
+    fn later_entries_survive_a_non_utf8_line() {
+        let dir = TempDir::new("history-bytes");
+        let path = dir.join("history.jsonl");
+        append(&path, &entry(100, "Firefox", Outcome::Opened)).unwrap();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"time\":\xff\xfe\n")
+            .unwrap();
+        append(&path, &entry(200, "Work", Outcome::Opened)).unwrap();
+
+        let times: Vec<i64> = read(&path).unwrap().iter().map(|e| e.time).collect();
+        assert_eq!(times, [100, 200]);
+    }
+
+    #[test]
+    fn entry_records_outcome_and_redacts() {
+        let opts = HistoryOptions {
+            enabled: true,
+            redact_urls: true,
+        };
+        let trace = Trace {
+            pattern: None,
+            browser: Some("Firefox".into()),
+            dialog: true,
+            ..Default::default()
+        };
+        let e = Entry::new(
+            "https://example.com/private",
+            &opts,
+            trace,
+            &Err(crate::open::Canceled.into()),
+        );
+        assert_eq!(e.url, "example.com");
+        assert_eq!(e.outcome, Outcome::Canceled);
+        assert!(e.dialog && e.error.is_none());
+        assert_eq!(
+            serde_json::to_string(&e).unwrap(),
+            format!(
+                r#"{{"time":{},"url":"example.com","browser":"Firefox","dialog":true,"outcome":"canceled"}}"#,
+                e.time
+            )
+        );
+    }
+
+    #[test]
+    fn since_accepts_durations_and_dates() {
+        use chrono::TimeZone;
+        let now = chrono::Local
+            .with_ymd_and_hms(2025, 9, 10, 12, 0, 0)
+            .unwrap();
+        assert_eq!(parse_since("2h", now).unwrap(), now.timestamp() - 7200);
+        assert_eq!(parse_since("1w", now).unwrap(), now.timestamp() - 7 * 86400);
+        let day = chrono::Local.with_ymd_and_hms(2025, 9, 1, 0, 0, 0).unwrap();
+        assert_eq!(parse_since("2025-09-01", now).unwrap(), day.timestamp());
+        assert_eq!(
+            parse_since("2025-09-01 14:30", now).unwrap(),
+            day.timestamp() + 14 * 3600 + 1800
+        );
+        assert!(parse_since("3y", now).is_err());
+        assert!(parse_since("yesterday", now).is_err());
+        assert!(parse_since("0d", now).is_err());
+        assert!(parse_since("-5d", now).is_err());
+        assert!(parse_since("999999999999999w", now).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use crate::asset::{Asset, Icon};
 use crate::config::ensure_config;
-use crate::paths::{dbus_service_dir, dbus_service_path, desktop_entry_path, icon_path};
+use crate::paths::{
+    dbus_service_dir, dbus_service_path, desktop_entry_path, icon_path, systemd_user_unit_path,
+};
 use crate::state::{InstallState, write_state};
 use anyhow::{Context, Result};
 use std::path::PathBuf;
@@ -9,7 +11,9 @@ pub fn install() -> Result<()> {
     ensure_config().context("Failed to create default configuration")?;
     install_icons().context("Failed to install icons")?;
     let desktop_entry_path = create_desktop_entry().context("Failed to create desktop entry")?;
-    create_dbus_service().context("Failed to install D-Bus activation service")?;
+    let exec_path = current_exe_path();
+    create_systemd_unit(&exec_path).context("Failed to install systemd user unit")?;
+    create_dbus_service(&exec_path).context("Failed to install D-Bus activation service")?;
     // Best-effort backup of previous default browser before we change it
     backup_previous_default_browser().ok();
     make_default_browser(desktop_entry_path).context("Failed to set as default browser")?;
@@ -70,8 +74,9 @@ fn create_desktop_entry() -> Result<PathBuf> {
         })?;
     }
 
-    let desktop_entry_content = Asset::get("muxie.desktop")
-        .with_context(|| format!("Failed to get embedded desktop entry: {}", "muxie.desktop"))?
+    let desktop_entry_name = format!("{}.desktop", crate::daemon::DBUS_SERVICE);
+    let desktop_entry_content = Asset::get(&desktop_entry_name)
+        .with_context(|| format!("Failed to get embedded desktop entry: {desktop_entry_name}"))?
         .data;
 
     std::fs::write(&desktop_entry_path, desktop_entry_content).with_context(|| {
@@ -80,10 +85,43 @@ fn create_desktop_entry() -> Result<PathBuf> {
             desktop_entry_path.display()
         )
     })?;
+    // The entry used to be called muxie.desktop; the new one replaces it as default browser
+    let _ = std::fs::remove_file(crate::paths::legacy_desktop_entry_path());
     Ok(desktop_entry_path)
 }
 
-fn create_dbus_service() -> Result<PathBuf> {
+/// Absolute path to the current executable, for reliable D-Bus and systemd activation.
+fn current_exe_path() -> String {
+    std::env::current_exe()
+        .map_err(anyhow::Error::from)
+        .and_then(|p| {
+            p.to_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Executable path contains invalid UTF-8"))
+        })
+        .unwrap_or_else(|_| "muxie".to_string())
+}
+
+fn create_systemd_unit(exec_path: &str) -> Result<PathBuf> {
+    let unit_path = systemd_user_unit_path();
+    if let Some(dir) = unit_path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| {
+            format!(
+                "Failed to create systemd user unit directory: {}",
+                dir.display()
+            )
+        })?;
+    }
+    std::fs::write(&unit_path, crate::systemd::unit_contents(exec_path))
+        .with_context(|| format!("Failed to write systemd user unit: {}", unit_path.display()))?;
+    // Best effort: systemd may not be running (e.g. non-systemd distributions)
+    if let Err(e) = crate::systemd::systemctl(&["daemon-reload"]) {
+        eprintln!("Warning: {e}");
+    }
+    Ok(unit_path)
+}
+
+fn create_dbus_service(exec_path: &str) -> Result<PathBuf> {
     let service_path = dbus_service_path();
     if let Some(dir) = service_path.parent() {
         std::fs::create_dir_all(dir).with_context(|| {
@@ -93,22 +131,9 @@ fn create_dbus_service() -> Result<PathBuf> {
             )
         })?;
     }
-    // Resolve absolute path to current executable for reliable D-Bus activation.
-    let exec_path = std::env::current_exe()
-        .map_err(anyhow::Error::from)
-        .and_then(|p| {
-            p.to_str()
-                .map(|s| s.to_string())
-                .ok_or_else(|| anyhow::anyhow!("Executable path contains invalid UTF-8"))
-        })
-        .unwrap_or_else(|_| "muxie".to_string());
 
     // Write service file (overwrites if it already exists)
-    let content = format!(
-        "[D-BUS Service]\nName={}\nExec={} daemon run\n",
-        crate::daemon::DBUS_SERVICE,
-        exec_path
-    );
+    let content = crate::systemd::dbus_service_contents(exec_path);
     std::fs::write(&service_path, content).with_context(|| {
         format!(
             "Failed to write D-Bus service file: {}",
@@ -150,7 +175,7 @@ fn run_xdg_settings_with_diagnostics(args: &[&str]) {
                 let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
                 let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
                 eprintln!(
-                    "Warning: xdg-settings {:?} exited with code {:?}\nstdout: {}\nstderr: {}\nHints:\n  - Ensure xdg-utils is installed and your desktop environment is supported.\n  - Try: 'xdg-settings get default-web-browser' and 'xdg-settings check default-web-browser'.\n  - You can set the default browser manually via your system settings or with: xdg-settings set default-web-browser muxie.desktop",
+                    "Warning: xdg-settings {:?} exited with code {:?}\nstdout: {}\nstderr: {}\nHints:\n  - Ensure xdg-utils is installed and your desktop environment is supported.\n  - Try: 'xdg-settings get default-web-browser' and 'xdg-settings check default-web-browser'.\n  - You can set the default browser manually via your system settings or with: xdg-settings set default-web-browser xyz.adf.Muxie.desktop",
                     args,
                     output.status.code().unwrap(),
                     if stdout.is_empty() {
@@ -0,0 +1,327 @@
+use crate::config::{LogLevel, LogOutput, LoggingOptions};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+pub(crate) const ROUTING: &str = "routing";
+pub(crate) const DIALOG: &str = "dialog";
+pub(crate) const DBUS: &str = "dbus";
+pub(crate) const WATCHER: &str = "watcher";
+pub(crate) const DAEMON: &str = "daemon";
+
+/// Targets accepted in `[logging] targets`.
+pub(crate) const TARGETS: &[&str] = &[ROUTING, DIALOG, DBUS, WATCHER, DAEMON];
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Configured levels, each raised to at least the level implied by `-v`.
+#[derive(Debug, PartialEq)]
+struct Filter {
+    default: LevelFilter,
+    targets: BTreeMap<String, LevelFilter>,
+}
+
+impl Filter {
+    fn new(opts: &LoggingOptions, verbose: u8) -> Self {
+        let floor = match verbose {
+            0 => LevelFilter::Off,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        };
+        Filter {
+            default: LevelFilter::from(opts.level).max(floor),
+            targets: opts
+                .targets
+                .iter()
+                .map(|(target, level)| (target.clone(), LevelFilter::from(*level).max(floor)))
+                .collect(),
+        }
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets.get(target).copied().unwrap_or(self.default)
+    }
+
+    fn max(&self) -> LevelFilter {
+        self.targets
+            .values()
+            .copied()
+            .fold(self.default, |a, b| a.max(b))
+    }
+}
+
+enum Sink {
+    Stderr,
+    Journald(UnixDatagram),
+}
+
+/// Append-only log file that is renamed to `<name>.1` once it exceeds `max` bytes.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max: u64) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file,
+            size,
+            max,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max {
+            std::fs::rename(&self.path, rotated_path(&self.path))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = 0;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+struct State {
+    filter: Filter,
+    sink: Sink,
+    file: Option<RotatingFile>,
+}
+
+impl State {
+    fn new(opts: &LoggingOptions, verbose: u8) -> Self {
+        let journal = match opts.output {
+            LogOutput::Stderr => None,
+            LogOutput::Journald => journal_socket(),
+            LogOutput::Auto if stderr_is_journal() => journal_socket(),
+            LogOutput::Auto => None,
+        };
+        let file = if opts.file {
+            let path = crate::paths::log_file_path();
+            match RotatingFile::open(path.clone(), opts.file_max_kb.saturating_mul(1024)) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    eprintln!("Warning: cannot open log file {}: {e}", path.display());
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        State {
+            filter: Filter::new(opts, verbose),
+            sink: journal.map_or(Sink::Stderr, Sink::Journald),
+            file,
+        }
+    }
+}
+
+struct Logger {
+    verbose: u8,
+    state: Mutex<State>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Install the global logger. `verbose` is the `-v` count and applies for the lifetime
+/// of the process.
+pub(crate) fn init(opts: &LoggingOptions, verbose: u8) {
+    let logger = LOGGER.get_or_init(|| Logger {
+        verbose,
+        state: Mutex::new(State::new(opts, verbose)),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(logger.state.lock().unwrap().filter.max());
+    }
+}
+
+/// Apply changed `[logging]` settings, e.g. after a configuration reload.
+pub(crate) fn reconfigure(opts: &LoggingOptions) {
+    if let Some(logger) = LOGGER.get() {
+        let state = State::new(opts, logger.verbose);
+        log::set_max_level(state.filter.max());
+        *logger.state.lock().unwrap() = state;
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let state = self.state.lock().unwrap();
+        metadata.level() <= state.filter.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        let mut state = self.state.lock().unwrap();
+        if record.level() > state.filter.level_for(record.target()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let line = format_line(record.level(), record.target(), &message);
+        let sent = match &state.sink {
+            Sink::Journald(sock) => sock
+                .send(&journal_entry(record.level(), record.target(), &message))
+                .is_ok(),
+            Sink::Stderr => false,
+        };
+        if !sent {
+            eprintln!("{line}");
+        }
+        if let Some(file) = &mut state.file {
+            let stamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f");
+            let _ = file.write_line(&format!("{stamp} {line}\n"));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn format_line(level: Level, target: &str, message: &str) -> String {
+    format!("{level:<5} {target}: {message}")
+}
+
+fn journal_socket() -> Option<UnixDatagram> {
+    let sock = UnixDatagram::unbound().ok()?;
+    sock.connect(JOURNAL_SOCKET).ok()?;
+    Some(sock)
+}
+
+/// systemd sets `JOURNAL_STREAM=<device>:<inode>` for services whose stderr is connected
+/// to the journal.
+fn stderr_is_journal() -> bool {
+    use std::os::fd::AsFd;
+    use std::os::unix::fs::MetadataExt;
+    let Ok(value) = std::env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Some((dev, ino)) = value.split_once(':') else {
+        return false;
+    };
+    let (Ok(dev), Ok(ino)) = (dev.parse::<u64>(), ino.parse::<u64>()) else {
+        return false;
+    };
+    let Ok(fd) = std::io::stderr().as_fd().try_clone_to_owned() else {
+        return false;
+    };
+    File::from(fd)
+        .metadata()
+        .is_ok_and(|m| m.dev() == dev && m.ino() == ino)
+}
+
+/// Entry in the journal's native protocol. Values containing newlines use the
+/// length-prefixed binary form.
+fn journal_entry(level: Level, target: &str, message: &str) -> Vec<u8> {
+    let priority = match level {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    };
+    let mut buf = Vec::new();
+    for (key, value) in [
+        ("PRIORITY", priority),
+        ("SYSLOG_IDENTIFIER", "muxie"),
+        ("MUXIE_TARGET", target),
+        ("MESSAGE", message),
+    ] {
+        buf.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            buf.push(b'\n');
+            buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            buf.push(b'=');
+        }
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    #[test]
+    fn filter_applies_targets_and_verbosity() {
+        let opts = LoggingOptions {
+            level: LogLevel::Warn,
+            targets: BTreeMap::from([
+                (ROUTING.to_string(), LogLevel::Trace),
+                (DIALOG.to_string(), LogLevel::Off),
+            ]),
+            ..Default::default()
+        };
+        let filter = Filter::new(&opts, 0);
+        assert_eq!(filter.level_for(ROUTING), LevelFilter::Trace);
+        assert_eq!(filter.level_for(DIALOG), LevelFilter::Off);
+        assert_eq!(filter.level_for(DBUS), LevelFilter::Warn);
+        assert_eq!(filter.max(), LevelFilter::Trace);
+
+        let filter = Filter::new(&opts, 1);
+        assert_eq!(filter.level_for(DIALOG), LevelFilter::Debug);
+        assert_eq!(filter.level_for(DBUS), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn journal_entry_encoding() {
+        let entry = journal_entry(Level::Warn, ROUTING, "one");
+        assert_eq!(
+            entry,
+            b"PRIORITY=4\nSYSLOG_IDENTIFIER=muxie\nMUXIE_TARGET=routing\nMESSAGE=one\n"
+        );
+        let entry = journal_entry(Level::Info, DBUS, "a\nb");
+        let mut expected =
+            b"PRIORITY=6\nSYSLOG_IDENTIFIER=muxie\nMUXIE_TARGET=dbus\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn log_file_rotates() {
+        let dir = TempDir::new("log");
+        let path = dir.join("muxie.log");
+        let mut file = RotatingFile::open(path.clone(), 10).unwrap();
+        file.write_line("12345678\n").unwrap();
+        file.write_line("abc\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "abc\n");
+        assert_eq!(
+            std::fs::read_to_string(rotated_path(&path)).unwrap(),
+            "12345678\n"
+        );
+    }
+}
@@ -3,59 +3,101 @@ mod asset;
 mod browser;
 mod cli;
 mod client;
+mod condition;
 mod config;
 mod daemon;
+mod desktop;
+mod diagnostics;
 mod dialog;
+mod edit;
+mod history;
 #[cfg(feature = "self-install")]
 mod install;
+mod logging;
+mod migrate;
+mod network;
 mod notify;
 mod open;
 mod paths;
 mod pattern;
+mod process;
+mod route;
+mod socket;
+mod sources;
 #[cfg(feature = "self-install")]
 mod state;
+mod stats;
+mod systemd;
 #[cfg(feature = "self-install")]
 mod uninstall;
 mod util;
 
-use crate::client::MuxieClient;
 #[cfg(feature = "self-install")]
 use crate::install::install;
 use crate::open::open_url;
 #[cfg(feature = "self-install")]
 use crate::uninstall::uninstall;
 use clap::Parser;
-use cli::{Cli, Commands, ConfigCommands, DaemonCommands};
+use cli::{
+    BrowserCommands, Cli, Commands, ConfigCommands, DaemonCommands, HistoryCommands, RuleCommands,
+};
+
+/// `muxie open`: ask the daemon first. On cancel or timeout, do not fall back (the daemon may
+/// still be showing its dialog); on other errors, fall back to in-process open unless
+/// `--daemon-only` is given.
+fn open_command(url: &str, no_daemon: bool, daemon_only: bool, cli: &Cli) -> anyhow::Result<()> {
+    if no_daemon {
+        return open_url(url, cli.no_notify);
+    }
+    let timeout_secs = config::read_config()
+        .map(|cfg| cfg.client.timeout_secs)
+        .unwrap_or_else(|_| config::ClientOptions::default().timeout_secs);
+    let timeout = (timeout_secs > 0).then(|| std::time::Duration::from_secs(timeout_secs));
+    match client::connect(timeout).and_then(|c| c.open_url(url)) {
+        Ok(()) => Ok(()),
+        Err(err) if err.downcast_ref::<open::Canceled>().is_some() => {
+            // Canceled by user via dialog; do not fall back.
+            eprintln!("Open canceled");
+            Err(anyhow::anyhow!("canceled"))
+        }
+        Err(err) if err.downcast_ref::<client::Timeout>().is_some() => {
+            Err(err
+                .context("Giving up on the daemon (see [client] timeout_secs, or use --no-daemon)"))
+        }
+        Err(err) if daemon_only => Err(err.context("Daemon unavailable or failed")),
+        Err(err) => {
+            log::warn!(
+                target: logging::ROUTING,
+                "Daemon unavailable or failed ({err}), opening directly"
+            );
+            open_url(url, cli.no_notify)
+        }
+    }
+}
 
 fn main() {
     let cli = Cli::parse();
+    if let Some(path) = &cli.config {
+        paths::set_config_path(path);
+    }
+    let logging = config::read_config()
+        .map(|cfg| cfg.logging)
+        .unwrap_or_default();
+    logging::init(&logging, cli.verbose);
 
     let result = match &cli.command {
         #[cfg(feature = "self-install")]
         Commands::Install {} => install(),
-        Commands::Open { url: None } => {
+        Commands::Open { url: None, .. } => {
             eprintln!("Error: No URL provided to open");
             std::process::exit(1);
         }
-        Commands::Open { url: Some(url) } => {
-            // Try daemon first; on cancel, do not fall back. On other errors, fall back to in-process open.
-            match client::ZbusClient::new().and_then(|c| c.open_url(url)) {
-                Ok(()) => Ok(()),
-                Err(err) => {
-                    let es = err.to_string();
-                    if es.contains(crate::open::CANCELED_ERR_MARKER) {
-                        // Canceled by user via dialog; do not fall back.
-                        eprintln!("Open canceled");
-                        Err(anyhow::anyhow!("canceled"))
-                    } else {
-                        eprintln!(
-                            "Daemon unavailable or failed ({err}). Falling back to direct open..."
-                        );
-                        open_url(url, cli.no_notify, cli.verbose)
-                    }
-                }
-            }
-        }
+        Commands::Open {
+            url: Some(url),
+            no_daemon,
+            daemon_only,
+        } => open_command(url, *no_daemon, *daemon_only, &cli),
+        Commands::Route { url } => route::route(url, cli.verbose),
         #[cfg(feature = "self-install")]
         Commands::Uninstall {
             yes,
@@ -76,27 +118,25 @@ fn main() {
                     Err(anyhow::anyhow!("config create failed"))
                 }
             },
-            ConfigCommands::Validate {} => match config::read_config() {
-                Ok(cfg) => {
-                    let result = cfg.validate(true);
-                    if result.is_empty() {
-                        println!("Configuration is valid");
-                        std::process::exit(0);
-                    } else {
-                        result.print();
-                        std::process::exit(1);
-                    }
-                }
-                Err(err) => {
-                    eprintln!("Error validating configuration: {err}");
-                    std::process::exit(2);
-                }
-            },
+            ConfigCommands::Validate {
+                deny_warnings,
+                format,
+            } => std::process::exit(diagnostics::validate(*format, *deny_warnings)),
+            ConfigCommands::Show {} => sources::show(),
+            ConfigCommands::Schema {} => {
+                println!("{:#}", config::json_schema());
+                Ok(())
+            }
+            ConfigCommands::Migrate { write } => migrate::migrate(*write),
         },
         Commands::Daemon { command } => match command {
-            DaemonCommands::Run {} => daemon::run(cli.no_notify, cli.verbose),
+            DaemonCommands::Run {} => daemon::run(cli.no_notify),
             DaemonCommands::Status {} => {
-                match client::ZbusClient::is_running() {
+                match client::ZbusClient::is_running().or_else(|err| {
+                    // No session bus: ask the socket listener instead
+                    let client = client::SocketClient::new().map_err(|_| err)?;
+                    Ok::<_, anyhow::Error>(client.status().is_ok())
+                }) {
                     Ok(true) => {
                         println!("Muxie daemon is running");
                         Ok(())
@@ -112,7 +152,14 @@ fn main() {
                     }
                 }
             }
-            DaemonCommands::Reload {} => match client::ZbusClient::reload() {
+            DaemonCommands::Enable {} => systemd::enable(),
+            DaemonCommands::Disable {} => systemd::disable(),
+            DaemonCommands::Reload {} => match client::ZbusClient::reload().or_else(|err| {
+                match client::SocketClient::new() {
+                    Ok(client) if client::ZbusClient::new().is_err() => client.reload(),
+                    _ => Err(err),
+                }
+            }) {
                 Ok(true) => {
                     println!("Reloaded configuration");
                     Ok(())
@@ -127,6 +174,37 @@ fn main() {
                 }
             },
         },
+        Commands::History {
+            command: Some(HistoryCommands::Clear {}),
+            ..
+        } => history::clear(),
+        Commands::History {
+            command: None,
+            since,
+            browser,
+            json,
+        } => history::show(since.as_deref(), browser.as_deref(), *json),
+        Commands::Stats { days, json } => stats::stats(*days, *json),
+        Commands::Browser { command } => match command {
+            BrowserCommands::List { installed } => edit::list_browsers(*installed),
+            BrowserCommands::Add {
+                name,
+                executable,
+                args,
+            } => edit::add_browser(name, executable.as_deref(), args),
+            BrowserCommands::Remove { name } => edit::remove_browser(name),
+        },
+        Commands::Rule { command } => match command {
+            RuleCommands::List {} => edit::list_rules(),
+            RuleCommands::Add {
+                pattern,
+                browsers,
+                at,
+                prefer_running,
+            } => edit::add_rule(pattern, browsers, *at, *prefer_running),
+            RuleCommands::Remove { index } => edit::remove_rule(*index),
+            RuleCommands::Move { from, to } => edit::move_rule(*from, *to),
+        },
     };
 
     if let Err(err) = result {
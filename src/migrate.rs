@@ -0,0 +1,235 @@
+use crate::config::Config;
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+
+/// Schema version of the configuration written by this release. Bump it together with a
+/// new entry in `STEPS`.
+pub(crate) const CURRENT_VERSION: u32 = 1;
+
+/// `STEPS[n]` upgrades a version `n` document to version `n + 1`.
+const STEPS: [fn(&mut DocumentMut); CURRENT_VERSION as usize] = [v0_to_v1];
+
+/// Version 0 is the YAML format used before 0.2.0, where each pattern named a single
+/// `browser`.
+fn v0_to_v1(doc: &mut DocumentMut) {
+    let Some(patterns) = doc
+        .get_mut("patterns")
+        .and_then(|p| p.as_array_of_tables_mut())
+    else {
+        return;
+    };
+    for pattern in patterns.iter_mut() {
+        if pattern.contains_key("browsers") {
+            continue;
+        }
+        if let Some(browser) = pattern.remove("browser")
+            && let Some(value) = browser.as_value()
+        {
+            let mut browsers = toml_edit::Array::new();
+            browsers.push(value.clone());
+            pattern.insert("browsers", toml_edit::value(browsers));
+        }
+    }
+}
+
+/// Schema version of a parsed main file. Files without `version` are version 1, the
+/// first TOML format.
+pub(crate) fn version(table: &toml::Table) -> Result<u32> {
+    match table.get("version") {
+        None => Ok(1),
+        Some(toml::Value::Integer(v)) => {
+            u32::try_from(*v).map_err(|_| anyhow::anyhow!("Invalid configuration version {v}"))
+        }
+        Some(other) => bail!("Configuration version must be an integer, not {other}"),
+    }
+}
+
+/// Upgrade the document `text` from `version` to `CURRENT_VERSION`. Versions newer than
+/// this release are rejected rather than guessed at.
+pub(crate) fn upgrade(text: &str, version: u32) -> Result<DocumentMut> {
+    if version > CURRENT_VERSION {
+        bail!(
+            "Configuration version {version} is newer than this release of muxie supports (up to {CURRENT_VERSION}); please upgrade muxie"
+        );
+    }
+    let mut doc: DocumentMut = text.parse()?;
+    for step in &STEPS[version as usize..] {
+        step(&mut doc);
+    }
+    doc["version"] = toml_edit::value(i64::from(CURRENT_VERSION));
+    Ok(doc)
+}
+
+/// The pre-0.2.0 YAML configuration at `path` as a version 0 TOML document.
+fn legacy_document(path: &Path) -> Result<String> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read legacy config file: {}", path.display()))?;
+    let value: serde_yaml::Value = serde_yaml::from_str(&text).with_context(|| {
+        format!(
+            "Failed to parse legacy config file (YAML): {}",
+            path.display()
+        )
+    })?;
+    let mut table = toml::Table::try_from(value)
+        .with_context(|| format!("Legacy config file cannot be converted: {}", path.display()))?;
+    table.insert("version".to_string(), toml::Value::Integer(0));
+    Ok(toml::to_string(&table)?)
+}
+
+/// Read the legacy configuration, converted and upgraded in memory.
+pub(crate) fn load_legacy(path: &Path) -> Result<Config> {
+    let doc = upgrade(&legacy_document(path)?, 0)?;
+    toml::from_str(&doc.to_string())
+        .with_context(|| format!("Invalid legacy configuration: {}", path.display()))
+}
+
+/// The legacy file to migrate: only used when the configuration file does not exist yet.
+fn legacy_source(path: &Path) -> Option<PathBuf> {
+    let legacy = crate::paths::legacy_config_path();
+    (!path.exists() && crate::paths::instance().is_none() && legacy.is_file()).then_some(legacy)
+}
+
+/// `<file>.bak`, or `<file>.bak.N` with the first free `N`.
+fn backup_path(file: &Path) -> PathBuf {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let mut backup = file.with_file_name(format!("{name}.bak"));
+    let mut n = 2;
+    while backup.exists() {
+        backup = file.with_file_name(format!("{name}.bak.{n}"));
+        n += 1;
+    }
+    backup
+}
+
+/// Migrate `source` (the configuration file itself, or the legacy file) to `path`. Returns
+/// the version migrated from and the backup of the original, or `None` when `source` is
+/// already current. Without `write`, the migrated file is printed instead.
+fn migrate_file(
+    source: &Path,
+    text: &str,
+    path: &Path,
+    write: bool,
+) -> Result<Option<(u32, PathBuf)>> {
+    let table: toml::Table = toml::from_str(text)
+        .with_context(|| format!("Failed to parse config file (TOML): {}", source.display()))?;
+    let from = version(&table)?;
+    if from == CURRENT_VERSION && source == path {
+        return Ok(None);
+    }
+    let migrated = upgrade(text, from)?.to_string();
+    toml::from_str::<Config>(&migrated).context("Migrated configuration is invalid")?;
+    if !write {
+        print!("{migrated}");
+        return Ok(Some((from, PathBuf::new())));
+    }
+
+    let backup = backup_path(source);
+    if source == path {
+        std::fs::copy(source, &backup)
+            .with_context(|| format!("Failed to back up {}", source.display()))?;
+    } else if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create config directory: {}", parent.display()))?;
+    }
+    std::fs::write(path, migrated)
+        .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+    if source != path {
+        // Moving the legacy file aside also stops it from being detected again
+        std::fs::rename(source, &backup)
+            .with_context(|| format!("Failed to move {} aside", source.display()))?;
+    }
+    Ok(Some((from, backup)))
+}
+
+/// `muxie config migrate`: upgrade the configuration file, or the legacy YAML file, to the
+/// current schema. Prints the result unless `write` is set.
+pub(crate) fn migrate(write: bool) -> Result<()> {
+    let path = crate::paths::config_path();
+    let (source, text) = match legacy_source(&path) {
+        Some(legacy) => {
+            let text = legacy_document(&legacy)?;
+            (legacy, text)
+        }
+        None => {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+            (path.clone(), text)
+        }
+    };
+    match migrate_file(&source, &text, &path, write)? {
+        None => println!("{} is already at version {CURRENT_VERSION}", path.display()),
+        Some((from, _)) if !write => eprintln!(
+            "Migrated {} from version {from} to {CURRENT_VERSION} (not written; use --write to replace {})",
+            source.display(),
+            path.display()
+        ),
+        Some((from, backup)) => println!(
+            "Migrated {} from version {from} to {CURRENT_VERSION} into {}; the original is at {}",
+            source.display(),
+            path.display(),
+            backup.display()
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    #[test]
+    fn upgrades_and_rejects_future_versions() {
+        let parse = |text: &str| version(&toml::from_str(text).unwrap());
+        assert_eq!(parse("").unwrap(), 1);
+        assert_eq!(parse("version = 0").unwrap(), 0);
+        assert!(parse("version = -1").is_err());
+        assert!(parse("version = \"2\"").is_err());
+
+        let text = "version = 0\n\n[[patterns]]\n# Work links\npattern = \"work\"\nbrowser = \"Firefox\"\n\n[[patterns]]\npattern = \"docs\"\nbrowsers = [\"A\", \"B\"]\n";
+        let doc = upgrade(text, 0).unwrap().to_string();
+        assert!(doc.starts_with("version = 1\n"));
+        assert!(doc.contains("# Work links\npattern = \"work\"\nbrowsers = [\"Firefox\"]\n"));
+        assert!(doc.contains("browsers = [\"A\", \"B\"]"));
+
+        let err = upgrade("version = 99\n", 99).unwrap_err();
+        assert!(err.to_string().contains("newer than this release"));
+    }
+
+    #[test]
+    fn migrates_legacy_yaml_with_backup() {
+        let dir = TempDir::new("migrate");
+        let legacy = dir.join("muxie.toml");
+        let path = dir.join("muxie/muxie.toml");
+        std::fs::write(
+            &legacy,
+            "browsers:\n  - name: Firefox\n    executable: firefox\n    args: [\"-P\", \"work\"]\npatterns:\n  - pattern: \"*.example.com\"\n    browser: Firefox\n",
+        )
+        .unwrap();
+
+        let config = load_legacy(&legacy).unwrap();
+        assert_eq!(config.version, CURRENT_VERSION);
+        assert_eq!(config.browsers[0].args, ["-P", "work"]);
+        assert_eq!(config.patterns[0].browsers, ["Firefox"]);
+
+        let text = legacy_document(&legacy).unwrap();
+        let (from, backup) = migrate_file(&legacy, &text, &path, true).unwrap().unwrap();
+        assert_eq!(from, 0);
+        assert_eq!(backup, dir.join("muxie.toml.bak"));
+        assert!(!legacy.exists() && backup.exists());
+        let migrated: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(migrated.patterns, config.patterns);
+
+        // A current file is left alone; an old one is backed up next to itself
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(migrate_file(&path, &text, &path, true).unwrap().is_none());
+        std::fs::write(&path, "version = 0\n").unwrap();
+        let (_, backup) = migrate_file(&path, "version = 0\n", &path, true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(backup, dir.join("muxie/muxie.toml.bak"));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "version = 0\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "version = 1\n");
+    }
+}
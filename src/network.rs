@@ -0,0 +1,209 @@
+use crate::logging::ROUTING;
+use std::path::PathBuf;
+
+/// Read-only view of the active network state, used by `network` conditions.
+pub(crate) trait NetworkProbe {
+    /// Names of interfaces that are administratively up.
+    fn interfaces_up(&self) -> Vec<String>;
+    /// Interfaces carrying an IPv4 or IPv6 default route.
+    fn default_route_interfaces(&self) -> Vec<String>;
+    /// SSIDs of active Wi-Fi connections.
+    fn wifi_ssids(&self) -> Vec<String>;
+}
+
+/// Probe backed by sysfs/procfs, with Wi-Fi SSIDs queried from NetworkManager over the
+/// system D-Bus. All lookups are best effort and yield empty lists on failure.
+pub(crate) struct SysNetwork {
+    sys_class_net: PathBuf,
+    proc_net: PathBuf,
+}
+
+impl Default for SysNetwork {
+    fn default() -> Self {
+        SysNetwork {
+            sys_class_net: PathBuf::from("/sys/class/net"),
+            proc_net: PathBuf::from("/proc/net"),
+        }
+    }
+}
+
+const IFF_UP: u32 = 0x1;
+
+impl SysNetwork {
+    #[cfg(test)]
+    pub(crate) fn with_roots(
+        sys_class_net: impl Into<PathBuf>,
+        proc_net: impl Into<PathBuf>,
+    ) -> Self {
+        SysNetwork {
+            sys_class_net: sys_class_net.into(),
+            proc_net: proc_net.into(),
+        }
+    }
+}
+
+impl NetworkProbe for SysNetwork {
+    fn interfaces_up(&self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(&self.sys_class_net) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                // `operstate` is "unknown" for tun/wireguard devices, so check IFF_UP instead
+                std::fs::read_to_string(e.path().join("flags"))
+                    .ok()
+                    .and_then(|f| u32::from_str_radix(f.trim().trim_start_matches("0x"), 16).ok())
+                    .is_some_and(|flags| flags & IFF_UP != 0)
+            })
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn default_route_interfaces(&self) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        // Columns: Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+        if let Ok(text) = std::fs::read_to_string(self.proc_net.join("route")) {
+            for line in text.lines().skip(1) {
+                let cols: Vec<&str> = line.split_whitespace().collect();
+                if cols.len() >= 8 && cols[1] == "00000000" && cols[7] == "00000000" {
+                    out.push(cols[0].to_string());
+                }
+            }
+        }
+        // Columns: dest prefixlen src srcprefixlen nexthop metric refcnt use flags iface
+        if let Ok(text) = std::fs::read_to_string(self.proc_net.join("ipv6_route")) {
+            for line in text.lines() {
+                let cols: Vec<&str> = line.split_whitespace().collect();
+                if cols.len() >= 10
+                    && cols[1] == "00"
+                    && cols[0].bytes().all(|b| b == b'0')
+                    && cols[9] != "lo"
+                {
+                    out.push(cols[9].to_string());
+                }
+            }
+        }
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    fn wifi_ssids(&self) -> Vec<String> {
+        nm_wifi_ssids().unwrap_or_default()
+    }
+}
+
+/// Query NetworkManager for the SSIDs of the access points active Wi-Fi connections use.
+/// A connection or device that cannot be read is skipped.
+fn nm_wifi_ssids() -> zbus::Result<Vec<String>> {
+    use zbus::zvariant::OwnedObjectPath;
+
+    let conn = zbus::blocking::Connection::system()?;
+    let nm = nm_proxy(&conn, "/org/freedesktop/NetworkManager", NM)?;
+    let active: Vec<OwnedObjectPath> = nm.get_property("ActiveConnections")?;
+    let mut out = Vec::new();
+    for path in active {
+        match connection_ssids(&conn, &path) {
+            Ok(ssids) => out.extend(ssids),
+            Err(e) => log::debug!(target: ROUTING, "Skipping network connection {path}: {e}"),
+        }
+    }
+    out.sort();
+    out.dedup();
+    Ok(out)
+}
+
+const NM: &str = "org.freedesktop.NetworkManager";
+
+fn nm_proxy<'a>(
+    conn: &zbus::blocking::Connection,
+    path: &'a str,
+    interface: &'a str,
+) -> zbus::Result<zbus::blocking::Proxy<'a>> {
+    zbus::blocking::Proxy::new(conn, NM, path, interface)
+}
+
+/// SSIDs of the devices of one active connection; none unless it is a Wi-Fi connection.
+fn connection_ssids(conn: &zbus::blocking::Connection, path: &str) -> zbus::Result<Vec<String>> {
+    use zbus::zvariant::OwnedObjectPath;
+
+    let ac = nm_proxy(
+        conn,
+        path,
+        "org.freedesktop.NetworkManager.Connection.Active",
+    )?;
+    let kind: String = ac.get_property("Type")?;
+    if kind != "802-11-wireless" {
+        return Ok(Vec::new());
+    }
+    let devices: Vec<OwnedObjectPath> = ac.get_property("Devices")?;
+    let mut out = Vec::new();
+    for device in devices {
+        match device_ssid(conn, &device) {
+            Ok(ssid) => out.extend(ssid),
+            Err(e) => log::debug!(target: ROUTING, "Skipping Wi-Fi device {device}: {e}"),
+        }
+    }
+    Ok(out)
+}
+
+/// SSID of the access point a Wi-Fi device is associated with, if any.
+fn device_ssid(conn: &zbus::blocking::Connection, device: &str) -> zbus::Result<Option<String>> {
+    use zbus::zvariant::OwnedObjectPath;
+
+    let wireless = nm_proxy(
+        conn,
+        device,
+        "org.freedesktop.NetworkManager.Device.Wireless",
+    )?;
+    let ap: OwnedObjectPath = wireless.get_property("ActiveAccessPoint")?;
+    // "/" while the device is not associated
+    if ap.as_str() == "/" {
+        return Ok(None);
+    }
+    let ap = nm_proxy(
+        conn,
+        ap.as_str(),
+        "org.freedesktop.NetworkManager.AccessPoint",
+    )?;
+    let ssid: Vec<u8> = ap.get_property("Ssid")?;
+    Ok((!ssid.is_empty()).then(|| String::from_utf8_lossy(&ssid).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    #[test]
+    fn reads_interfaces_and_default_routes() {
+        let root = TempDir::new("net");
+        let sys = root.join("sys");
+        let proc_net = root.join("proc");
+        for (name, flags) in [("eth0", "0x1003"), ("wg0", "0x91"), ("tun0", "0x1002")] {
+            std::fs::create_dir_all(sys.join(name)).unwrap();
+            std::fs::write(sys.join(name).join("flags"), format!("{flags}\n")).unwrap();
+        }
+        std::fs::create_dir_all(&proc_net).unwrap();
+        std::fs::write(
+            proc_net.join("route"),
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+             eth0\t00000000\t010200C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+             eth0\t000200C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            proc_net.join("ipv6_route"),
+            "00000000000000000000000000000000 00 00000000000000000000000000000000 00 fe800000000000000000000000000001 00000064 00000001 00000000 00000003     wg0\n\
+             00000000000000000000000000000001 80 00000000000000000000000000000000 00 00000000000000000000000000000000 00000000 00000001 00000000 00200001       lo\n",
+        )
+        .unwrap();
+
+        let probe = SysNetwork::with_roots(&sys, &proc_net);
+        let mut up = probe.interfaces_up();
+        up.sort();
+        assert_eq!(up, vec!["eth0", "wg0"]);
+        assert_eq!(probe.default_route_interfaces(), vec!["eth0", "wg0"]);
+    }
+}
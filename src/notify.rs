@@ -18,6 +18,16 @@ pub(crate) fn redact_url(url: &str) -> String {
     }
 }
 
+/// `url` as shown in notifications and logs: only the host when `redact` is set
+/// (`notifications.redact_urls`).
+pub(crate) fn shown_url(url: &str, redact: bool) -> String {
+    if redact {
+        redact_url(url)
+    } else {
+        url.to_string()
+    }
+}
+
 pub(crate) fn notify_error(
     url: &str,
     rule: &str,
@@ -28,11 +38,7 @@ pub(crate) fn notify_error(
     if !prefs.enabled {
         return;
     }
-    let shown_url = if prefs.redact_urls {
-        redact_url(url)
-    } else {
-        url.to_string()
-    };
+    let shown_url = shown_url(url, prefs.redact_urls);
     let title = "Muxie: Failed to open";
     let body = format!("{shown_url} via rule '{rule}' → {browser}: {error_summary}");
     // Best-effort notification; swallow all errors
@@ -44,6 +50,51 @@ pub(crate) fn notify_error(
         .show();
 }
 
+/// Notify that a matching rule was skipped because one of its conditions did not hold.
+pub(crate) fn notify_skipped(url: &str, rule: &str, reason: &str, prefs: &NotifyPrefs) {
+    if !prefs.enabled {
+        return;
+    }
+    let shown_url = shown_url(url, prefs.redact_urls);
+    let title = "Muxie: Rule not applied";
+    let body = format!("{shown_url}: rule '{rule}' skipped, {reason}");
+    // Best-effort notification; swallow all errors
+    let _ = notify_rust::Notification::new()
+        .summary(title)
+        .body(&body)
+        .appname("Muxie")
+        .icon("muxie")
+        .show();
+}
+
+/// Notify that a changed configuration file was rejected; the previous configuration
+/// stays active.
+pub(crate) fn notify_config_rejected(errors: &[crate::config::ValidationError], enabled: bool) {
+    const MAX_LISTED: usize = 5;
+    if !enabled {
+        return;
+    }
+    let mut lines: Vec<String> = errors
+        .iter()
+        .take(MAX_LISTED)
+        .map(|e| match &e.path {
+            Some(path) => format!("{path}: {}", e.message),
+            None => e.message.clone(),
+        })
+        .collect();
+    if errors.len() > MAX_LISTED {
+        lines.push(format!("…and {} more", errors.len() - MAX_LISTED));
+    }
+    let title = "Muxie: Configuration not reloaded";
+    // Best-effort notification; swallow all errors
+    let _ = notify_rust::Notification::new()
+        .summary(title)
+        .body(&lines.join("\n"))
+        .appname("Muxie")
+        .icon("muxie")
+        .show();
+}
+
 // Dependency trait for notifications and a default impl.
 pub(crate) trait Notifier {
     fn notify_error(
@@ -54,6 +105,12 @@ pub(crate) trait Notifier {
         error_summary: &str,
         prefs: &NotifyPrefs,
     );
+
+    fn notify_skipped(&self, url: &str, rule: &str, reason: &str, prefs: &NotifyPrefs);
+
+    /// Called after a URL was handed to a browser. `rule` is `None` for the default browser.
+    /// Successful opens are not shown on the desktop.
+    fn notify_opened(&self, _url: &str, _rule: Option<&str>, _browser: &str) {}
 }
 
 #[derive(Default, Clone, Copy)]
@@ -71,6 +128,10 @@ impl Notifier for DefaultNotifier {
         // Delegate to the module function for actual delivery
         notify_error(url, rule, browser, error_summary, prefs)
     }
+
+    fn notify_skipped(&self, url: &str, rule: &str, reason: &str, prefs: &NotifyPrefs) {
+        notify_skipped(url, rule, reason, prefs)
+    }
 }
 
 #[cfg(test)]
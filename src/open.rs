@@ -1,8 +1,14 @@
 use crate::browser::Browser;
+use crate::condition::Mismatch;
 use crate::config::{Config, read_config};
+use crate::desktop::{DesktopProbe, SysDesktop};
 use crate::dialog::Selector;
+use crate::history::Trace;
+use crate::logging::{DIALOG, ROUTING};
+use crate::network::{NetworkProbe, SysNetwork};
 use crate::notify::{DefaultNotifier, Notifier, NotifyPrefs};
-use crate::pattern::Pattern;
+use crate::process::{ProcProbe, ProcessProbe, SourceApp};
+use crate::route::Outcome;
 use anyhow::{Context, Result, bail};
 
 pub(crate) trait UrlOpener {
@@ -13,37 +19,105 @@ pub(crate) struct DefaultOpener;
 
 impl UrlOpener for DefaultOpener {
     fn open(&self, browser: &Browser, url: &str) -> Result<()> {
-        let mut command = std::process::Command::new(&browser.executable);
-        let mut url_arg_found = false;
-        for arg in &browser.args {
-            match arg.as_str() {
-                "%u" | "%U" => {
-                    url_arg_found = true;
-                    command.arg(url);
-                }
-                _ => {
-                    command.arg(arg);
-                }
-            }
-        }
-        if !url_arg_found {
-            command.arg(url);
-        }
-        command.spawn()?;
+        let argv = browser.command_line(url);
+        std::process::Command::new(&argv[0])
+            .args(&argv[1..])
+            .spawn()?;
         Ok(())
     }
 }
 
-pub(crate) const CANCELED_ERR_MARKER: &str = "MUXIE:CANCELED";
+/// Error returned when the user dismisses the selection dialog.
+#[derive(Debug)]
+pub(crate) struct Canceled;
+
+impl std::fmt::Display for Canceled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Operation canceled by user")
+    }
+}
+
+impl std::error::Error for Canceled {}
+
+/// System state consulted while routing a URL.
+pub(crate) struct RouteContext {
+    pub(crate) processes: Box<dyn ProcessProbe>,
+    /// Application that asked to open the URL, if known.
+    pub(crate) source: Option<SourceApp>,
+    /// Local wall-clock time used for `when` conditions.
+    pub(crate) now: chrono::NaiveDateTime,
+    pub(crate) network: Box<dyn NetworkProbe>,
+    /// Caller's environment and compositor state for `env`/`workspace` conditions.
+    pub(crate) desktop: Box<dyn DesktopProbe>,
+}
+
+impl RouteContext {
+    /// Context backed by the live system.
+    pub(crate) fn system() -> Self {
+        RouteContext {
+            processes: Box::new(ProcProbe::default()),
+            source: None,
+            now: chrono::Local::now().naive_local(),
+            network: Box::new(SysNetwork::default()),
+            desktop: Box::new(SysDesktop::current()),
+        }
+    }
+
+    /// Attribute the request to the application behind `pid` (see `ProcProbe::source_app`).
+    pub(crate) fn with_source_pid(mut self, pid: Option<u32>) -> Self {
+        self.source = pid.and_then(|pid| ProcProbe::default().source_app(pid));
+        self
+    }
+
+    /// Evaluate `env`/`workspace` conditions in the environment of `pid` (e.g. the D-Bus
+    /// caller) instead of this process's environment.
+    pub(crate) fn with_caller_env(mut self, pid: Option<u32>) -> Self {
+        if let Some(pid) = pid {
+            self.desktop = Box::new(SysDesktop::for_pid(pid));
+        }
+        self
+    }
+}
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn open_url_with<O, N>(
     config: &Config,
     opener: &O,
     notifier: &N,
     selector: &dyn Selector,
+    ctx: &RouteContext,
     url: &str,
     no_notify: bool,
-    verbose: u8,
+) -> Result<()>
+where
+    O: UrlOpener,
+    N: Notifier,
+{
+    let mut trace = Trace::default();
+    let result = open_url_traced(
+        config, opener, notifier, selector, ctx, url, no_notify, &mut trace,
+    );
+    if config.history.enabled {
+        let entry = crate::history::Entry::new(url, &config.history, trace, &result);
+        if let Err(err) = crate::history::append(&crate::paths::history_path(), &entry) {
+            log::warn!(target: ROUTING, "Failed to record history: {err:#}");
+        }
+    }
+    result
+}
+
+/// Route and open `url`, recording the matched pattern, the browser and whether the
+/// dialog was shown in `trace`.
+#[allow(clippy::too_many_arguments)]
+fn open_url_traced<O, N>(
+    config: &Config,
+    opener: &O,
+    notifier: &N,
+    selector: &dyn Selector,
+    ctx: &RouteContext,
+    url: &str,
+    no_notify: bool,
+    trace: &mut Trace,
 ) -> Result<()>
 where
     O: UrlOpener,
@@ -56,110 +130,134 @@ where
         enabled: config.notifications.enabled && !no_notify,
         redact_urls: config.notifications.redact_urls,
     };
-    // Build a lookup map for browsers by name (preserve order separately)
-    let mut by_name: std::collections::HashMap<&str, &Browser> = std::collections::HashMap::new();
-    for b in &config.browsers {
-        by_name.insert(b.name.as_str(), b);
+    let shown_url = crate::notify::shown_url(url, config.notifications.redact_urls);
+    if let Some(source) = &ctx.source {
+        log::debug!(target: ROUTING, "Source application: {}", source.describe());
     }
 
-    for pat in &config.patterns {
-        if pat.browsers.is_empty() {
-            continue; // ignored pattern per PRD
-        }
-        if pat.pattern.matches(url) {
-            if verbose >= 1 {
-                eprintln!("Pattern '{}' matched", pat.pattern);
-            } // Resolve eligible browsers for this pattern (skip unknown names)
-            let mut eligible: Vec<&Browser> = Vec::new();
-            let mut eligible_names: Vec<String> = Vec::new();
-            for name in &pat.browsers {
-                if let Some(b) = by_name.get(name.as_str()) {
-                    eligible.push(*b);
-                    eligible_names.push(b.name.clone());
-                } else if verbose >= 1 {
-                    eprintln!("- Skipping unknown browser '{name}' in pattern");
-                }
+    for index in 0..config.patterns.len() {
+        let report = crate::route::assess(config, index, ctx, url);
+        let pat = report.entry;
+        let (candidates, fallback) = match report.outcome {
+            // Ignored per PRD, or none of its browsers are configured
+            Outcome::Ignored | Outcome::NoKnownBrowsers | Outcome::Skipped(Mismatch::Url) => {
+                continue;
             }
-
-            if eligible.is_empty() {
+            Outcome::Skipped(reason) => {
+                log::debug!(target: ROUTING, "Pattern '{}' skipped ({reason})", pat.pattern);
+                if let Mismatch::Network(why) = &reason {
+                    // An unmet network requirement is usually worth knowing about
+                    // (e.g. an intranet link while the VPN is down)
+                    notifier.notify_skipped(url, pat.pattern.as_str(), why, &notify_prefs);
+                }
                 continue;
             }
+            Outcome::Matched(candidates) => {
+                log::debug!(target: ROUTING, "Pattern '{}' matched", pat.pattern);
+                (candidates, false)
+            }
+            Outcome::Fallback(reason, candidates) => {
+                log::debug!(
+                    target: ROUTING,
+                    "Pattern '{}' matched, using fallback browsers ({reason})",
+                    pat.pattern
+                );
+                (candidates, true)
+            }
+        };
+        let offered = candidates.offered;
+        let mut order = candidates.browsers;
 
-            // Determine attempt order, possibly via selection dialog when 2+ options exist
-            let mut indices: Vec<usize> = (0..eligible.len()).collect();
-            if eligible.len() >= 2 {
-                let title = "Open with…";
-                let redacted = crate::notify::redact_url(url);
-                let message = format!("Choose a browser for: {}", redacted);
-                match selector.choose(title, &message, &eligible_names, 0) {
-                    Ok(Some(selected)) => {
-                        // Start from selected, then wrap around the rest in order
-                        let mut ordered = Vec::with_capacity(indices.len());
-                        ordered.push(selected);
-                        for i in (selected + 1)..indices.len() {
-                            ordered.push(i);
-                        }
-                        for i in 0..selected {
-                            ordered.push(i);
-                        }
-                        indices = ordered;
-                    }
-                    Ok(None) => {
-                        // User canceled: abort operation without notifications.
-                        bail!("{} Operation canceled by user", CANCELED_ERR_MARKER);
-                    }
-                    Err(err) => {
-                        if verbose >= 1 {
-                            eprintln!(
-                                "Selection dialog failed ({}); proceeding without prompt",
-                                err
-                            );
-                        }
-                        // Keep indices as default order
-                    }
+        // Possibly reorder via selection dialog when 2+ options exist
+        if offered >= 2 {
+            let title = "Open with…";
+            let redacted = crate::notify::redact_url(url);
+            let message = format!("Choose a browser for: {}", redacted);
+            let options: Vec<String> = order[..offered].iter().map(|b| b.name.clone()).collect();
+            let choice = selector.choose(title, &message, &options, 0);
+            trace.dialog |= choice.is_ok();
+            match choice {
+                Ok(Some(selected)) if selected < offered => {
+                    // Start from selected, then wrap around the rest of the offered
+                    // options in order, then any options that were not offered
+                    order[..offered].rotate_left(selected);
+                }
+                Ok(Some(_)) => {
+                    // Out-of-range selection; keep the default order
+                }
+                Ok(None) => {
+                    // User canceled: abort operation without notifications.
+                    return Err(Canceled.into());
+                }
+                Err(err) => {
+                    log::warn!(
+                        target: DIALOG,
+                        "Selection dialog failed ({err}); proceeding without prompt"
+                    );
+                    // Keep the default order
                 }
             }
+        }
 
-            for &idx in &indices {
-                let browser = eligible[idx];
-                if verbose >= 1 {
-                    eprintln!("- Trying browser '{}'", browser.name);
+        for browser in order {
+            log::debug!(target: ROUTING, "Trying browser '{}'", browser.name);
+            trace.pattern = Some(pat.pattern.clone());
+            trace.browser = Some(browser.name.clone());
+            trace.fallback = fallback;
+            match opener.open(browser, url) {
+                Ok(_) => {
+                    log::info!(
+                        target: ROUTING,
+                        "Opened {shown_url} with '{}' (pattern '{}')",
+                        browser.name,
+                        pat.pattern
+                    );
+                    notifier.notify_opened(url, Some(pat.pattern.as_str()), &browser.name);
+                    return Ok(());
                 }
-                match opener.open(browser, url) {
-                    Ok(_) => return Ok(()),
-                    Err(err) => {
-                        eprintln!(
-                            "Warning: Failed to open URL '{}' with browser '{}': {}",
-                            url, browser.name, err
-                        );
-                        eprintln!("Trying next browser...");
-                        notifier.notify_error(
-                            url,
-                            pat.pattern.as_str(),
-                            &browser.name,
-                            &format!("{err}"),
-                            &notify_prefs,
-                        );
-                    }
+                Err(err) => {
+                    log::warn!(
+                        target: ROUTING,
+                        "Failed to open {shown_url} with '{}': {err}; trying next browser",
+                        browser.name
+                    );
+                    notifier.notify_error(
+                        url,
+                        pat.pattern.as_str(),
+                        &browser.name,
+                        &format!("{err}"),
+                        &notify_prefs,
+                    );
                 }
             }
         }
     }
     let default_browser = &config.browsers[0];
-    if verbose >= 1 {
-        eprintln!(
-            "No patterns matched, using default browser '{}'",
-            default_browser.name
-        );
-    }
+    log::debug!(
+        target: ROUTING,
+        "No patterns matched, using default browser '{}'",
+        default_browser.name
+    );
+    trace.pattern = None;
+    trace.browser = Some(default_browser.name.clone());
+    trace.fallback = false;
+    // The URL is left out: history and D-Bus signals record this error under their own
+    // redaction settings
     let result = opener.open(default_browser, url).with_context(|| {
         format!(
-            "Failed to open URL '{}' with default browser '{}'",
-            url, default_browser.name
+            "Failed to open with default browser '{}'",
+            default_browser.name
         )
     });
 
-    if let Err(err) = &result {
+    if result.is_ok() {
+        log::info!(
+            target: ROUTING,
+            "Opened {shown_url} with default browser '{}'",
+            default_browser.name
+        );
+        notifier.notify_opened(url, None, &default_browser.name);
+    } else if let Err(err) = &result {
         notifier.notify_error(
             url,
             "default",
@@ -171,7 +269,7 @@ where
     result
 }
 
-pub(crate) fn open_url(url: &str, no_notify: bool, verbose: u8) -> Result<()> {
+pub(crate) fn open_url(url: &str, no_notify: bool) -> Result<()> {
     let cfg = read_config()?;
     let opener = DefaultOpener;
     let notifier = DefaultNotifier;
@@ -181,9 +279,9 @@ pub(crate) fn open_url(url: &str, no_notify: bool, verbose: u8) -> Result<()> {
         &opener,
         &notifier,
         selector.as_ref(),
+        &RouteContext::system().with_source_pid(Some(std::os::unix::process::parent_id())),
         url,
         no_notify,
-        verbose,
     )
 }
 
@@ -234,12 +332,16 @@ mod tests {
 
     struct FakeNotifier {
         notifications: RefCell<Vec<(String, String, String, TestPrefsCapture)>>, // (url, rule, browser, prefs)
+        skipped: RefCell<Vec<(String, String, String)>>, // (url, rule, reason)
+        opened: RefCell<Vec<(Option<String>, String)>>,  // (rule, browser)
     }
 
     impl FakeNotifier {
         fn new() -> Self {
             Self {
                 notifications: RefCell::new(Vec::new()),
+                skipped: RefCell::new(Vec::new()),
+                opened: RefCell::new(Vec::new()),
             }
         }
     }
@@ -266,6 +368,21 @@ mod tests {
             ));
             let _ = error_summary;
         }
+
+        fn notify_skipped(&self, url: &str, rule: &str, reason: &str, prefs: &NotifyPrefs) {
+            if !prefs.enabled {
+                return;
+            }
+            self.skipped
+                .borrow_mut()
+                .push((url.to_string(), rule.to_string(), reason.to_string()));
+        }
+
+        fn notify_opened(&self, _url: &str, rule: Option<&str>, browser: &str) {
+            self.opened
+                .borrow_mut()
+                .push((rule.map(String::from), browser.to_string()));
+        }
     }
 
     fn browser(name: &str) -> Browser {
@@ -273,6 +390,7 @@ mod tests {
             name: name.to_string(),
             executable: name.to_lowercase(),
             args: vec!["%u".to_string()],
+            locked: false,
         }
     }
 
@@ -281,8 +399,57 @@ mod tests {
             version: 1,
             browsers,
             patterns,
-            notifications: crate::config::Notifications::default(),
-            dialog: crate::config::DialogOptions::default(),
+            ..Default::default()
+        }
+    }
+
+    struct FakeProcesses(Vec<&'static str>);
+
+    impl ProcessProbe for FakeProcesses {
+        fn is_running(&self, browser: &Browser) -> bool {
+            self.0.contains(&browser.name.as_str())
+        }
+    }
+
+    fn ctx() -> RouteContext {
+        ctx_running(vec![])
+    }
+
+    fn ctx_running(names: Vec<&'static str>) -> RouteContext {
+        RouteContext {
+            processes: Box::new(FakeProcesses(names)),
+            source: None,
+            now: chrono::NaiveDate::from_ymd_opt(2025, 9, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+            network: Box::new(FakeNetwork(vec![])),
+            desktop: Box::new(FakeDesktop(None)),
+        }
+    }
+
+    struct FakeDesktop(Option<&'static str>);
+
+    impl DesktopProbe for FakeDesktop {
+        fn env_var(&self, _name: &str) -> Option<String> {
+            None
+        }
+        fn active_workspace(&self) -> Option<String> {
+            self.0.map(String::from)
+        }
+    }
+
+    struct FakeNetwork(Vec<&'static str>);
+
+    impl NetworkProbe for FakeNetwork {
+        fn interfaces_up(&self) -> Vec<String> {
+            self.0.iter().map(|s| s.to_string()).collect()
+        }
+        fn default_route_interfaces(&self) -> Vec<String> {
+            Vec::new()
+        }
+        fn wifi_ssids(&self) -> Vec<String> {
+            Vec::new()
         }
     }
 
@@ -346,6 +513,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "example.com".into(),
                 browsers: vec!["A".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -356,9 +524,9 @@ mod tests {
             &opener,
             &notifier,
             &NoopSelector,
+            &ctx(),
             "https://www.example.com",
             false,
-            0,
         );
         assert!(res.is_ok());
         assert_eq!(opener.opens.borrow().as_slice(), ["A"]);
@@ -372,6 +540,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "example.com".into(),
                 browsers: vec!["A".into(), "B".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -383,9 +552,9 @@ mod tests {
             &opener,
             &notifier,
             &SelectIdx(0),
+            &ctx(),
             "https://www.example.com/x",
             false,
-            0,
         );
         assert!(res.is_ok());
         assert_eq!(opener.opens.borrow().as_slice(), ["A", "B"]);
@@ -403,6 +572,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "nope".into(),
                 browsers: vec!["B".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -413,9 +583,9 @@ mod tests {
             &opener,
             &notifier,
             &NoopSelector,
+            &ctx(),
             "https://example.com",
             false,
-            0,
         );
         assert!(res.is_ok());
         assert_eq!(opener.opens.borrow().as_slice(), ["A"]);
@@ -429,6 +599,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "example.com".into(),
                 browsers: vec!["A".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -439,9 +610,9 @@ mod tests {
             &opener,
             &notifier,
             &NoopSelector,
+            &ctx(),
             "https://example.com",
             false,
-            0,
         );
         assert!(res.is_err());
         // Tried match then default (same browser index 0 twice)
@@ -466,6 +637,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "nope".into(),
                 browsers: vec!["A".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -476,9 +648,9 @@ mod tests {
             &opener,
             &notifier,
             &NoopSelector,
+            &ctx(),
             "https://example.com",
             false,
-            0,
         );
         assert!(res.is_err());
         assert_eq!(opener.opens.borrow().as_slice(), ["A"]);
@@ -496,6 +668,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "nope".into(),
                 browsers: vec!["A".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -506,9 +679,9 @@ mod tests {
             &opener,
             &notifier,
             &NoopSelector,
+            &ctx(),
             "https://example.com",
             true,
-            0,
         );
         assert!(res.is_err());
         assert!(notifier.notifications.borrow().is_empty());
@@ -524,9 +697,9 @@ mod tests {
             &opener,
             &notifier,
             &NoopSelector,
+            &ctx(),
             "https://example.com",
             false,
-            0,
         );
         assert!(res.is_err());
         assert!(opener.opens.borrow().is_empty());
@@ -540,6 +713,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "example.com".into(),
                 browsers: vec!["Missing".into(), "A".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -549,9 +723,9 @@ mod tests {
             &opener,
             &notifier,
             &NoopSelector,
+            &ctx(),
             "https://example.com",
             false,
-            0,
         );
         assert!(res.is_ok());
         assert_eq!(opener.opens.borrow().as_slice(), ["A"]);
@@ -564,6 +738,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "example.com".into(),
                 browsers: vec!["A".into(), "B".into(), "C".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -576,9 +751,9 @@ mod tests {
             &opener,
             &notifier,
             &selector,
+            &ctx(),
             "https://example.com",
             false,
-            0,
         );
         assert!(res.is_ok());
         assert_eq!(opener.opens.borrow().as_slice(), ["B", "C"]);
@@ -591,6 +766,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "example.com".into(),
                 browsers: vec!["A".into(), "B".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -601,11 +777,11 @@ mod tests {
             &opener,
             &notifier,
             &selector,
+            &ctx(),
             "https://example.com",
             false,
-            0,
         );
-        assert!(res.is_err());
+        assert!(res.unwrap_err().downcast_ref::<Canceled>().is_some());
         assert!(opener.opens.borrow().is_empty());
         assert!(notifier.notifications.borrow().is_empty());
     }
@@ -617,6 +793,7 @@ mod tests {
             vec![PatternEntry {
                 pattern: "example.com".into(),
                 browsers: vec!["A".into(), "B".into()],
+                ..Default::default()
             }],
         );
         let opener = FakeOpener::new();
@@ -628,11 +805,417 @@ mod tests {
             &opener,
             &notifier,
             &selector,
+            &ctx(),
+            "https://example.com",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["A"]);
+    }
+
+    #[test]
+    fn prefer_running_skips_dialog_when_one_is_running() {
+        let cfg = cfg_with(
+            vec![browser("A"), browser("B"), browser("C")],
+            vec![PatternEntry {
+                pattern: "example.com".into(),
+                browsers: vec!["A".into(), "B".into(), "C".into()],
+                prefer_running: true,
+                ..Default::default()
+            }],
+        );
+        let opener = FakeOpener::new();
+        let notifier = FakeNotifier::new();
+        // A cancel would abort; the dialog must not be shown at all
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &CancelSelector,
+            &ctx_running(vec!["B"]),
+            "https://example.com",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["B"]);
+    }
+
+    #[test]
+    fn prefer_running_prompts_among_running_then_falls_back() {
+        let cfg = cfg_with(
+            vec![browser("A"), browser("B"), browser("C")],
+            vec![PatternEntry {
+                pattern: "example.com".into(),
+                browsers: vec!["A".into(), "B".into(), "C".into()],
+                prefer_running: true,
+                ..Default::default()
+            }],
+        );
+        let opener = FakeOpener::new();
+        opener.queue_outcomes("C", vec![Err(anyhow!("fail C"))]);
+        opener.queue_outcomes("B", vec![Err(anyhow!("fail B"))]);
+        let notifier = FakeNotifier::new();
+        // Dialog offers [B, C]; choosing index 1 picks C
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &SelectIdx(1),
+            &ctx_running(vec!["B", "C"]),
+            "https://example.com",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["C", "B", "A"]);
+    }
+
+    #[test]
+    fn prefer_running_without_running_browsers_prompts_all() {
+        let cfg = cfg_with(
+            vec![browser("A"), browser("B")],
+            vec![PatternEntry {
+                pattern: "example.com".into(),
+                browsers: vec!["A".into(), "B".into()],
+                prefer_running: true,
+                ..Default::default()
+            }],
+        );
+        let opener = FakeOpener::new();
+        let notifier = FakeNotifier::new();
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &SelectIdx(1),
+            &ctx(),
+            "https://example.com",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["B"]);
+    }
+
+    #[test]
+    fn source_restricted_pattern_matches_only_that_source() {
+        let cfg = cfg_with(
+            vec![browser("A"), browser("B"), browser("C")],
+            vec![
+                PatternEntry {
+                    pattern: "*".into(),
+                    browsers: vec!["B".into()],
+                    source: vec!["slack".into()],
+                    ..Default::default()
+                },
+                PatternEntry {
+                    pattern: "*".into(),
+                    browsers: vec!["C".into()],
+                    source: vec!["org.mozilla.Thunderbird".into()],
+                    ..Default::default()
+                },
+            ],
+        );
+        let from = |exe: &str, app_id: Option<&str>| RouteContext {
+            source: Some(SourceApp {
+                pid: 10,
+                exe: Some(exe.into()),
+                app_id: app_id.map(String::from),
+            }),
+            ..ctx()
+        };
+        let notifier = FakeNotifier::new();
+
+        let opener = FakeOpener::new();
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &NoopSelector,
+            &from("thunderbird", Some("org.mozilla.Thunderbird")),
+            "https://example.com",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["C"]);
+
+        // Unknown source: neither restricted pattern applies, default browser is used
+        let opener = FakeOpener::new();
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &NoopSelector,
+            &ctx(),
             "https://example.com",
             false,
-            0,
         );
         assert!(res.is_ok());
         assert_eq!(opener.opens.borrow().as_slice(), ["A"]);
     }
+
+    #[test]
+    fn when_condition_selects_profile_by_time() {
+        let cfg = cfg_with(
+            vec![browser("Personal"), browser("Work")],
+            vec![PatternEntry {
+                pattern: "*".into(),
+                browsers: vec!["Work".into()],
+                when: Some(crate::config::When {
+                    days: vec!["mon-fri".into()],
+                    hours: Some("09:00-18:00".into()),
+                }),
+                ..Default::default()
+            }],
+        );
+        let notifier = FakeNotifier::new();
+        let at = |d: u32, h: u32| RouteContext {
+            now: chrono::NaiveDate::from_ymd_opt(2025, 9, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap(),
+            ..ctx()
+        };
+
+        // Monday noon: work hours
+        let opener = FakeOpener::new();
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &NoopSelector,
+            &at(1, 12),
+            "https://example.com",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["Work"]);
+
+        // Saturday noon: falls through to the default browser
+        let opener = FakeOpener::new();
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &NoopSelector,
+            &at(6, 12),
+            "https://example.com",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["Personal"]);
+    }
+
+    fn vpn_cfg(fallback: Vec<String>) -> Config {
+        cfg_with(
+            vec![browser("Personal"), browser("Work"), browser("Other")],
+            vec![PatternEntry {
+                pattern: "intranet.example.com".into(),
+                browsers: vec!["Work".into()],
+                network: Some(crate::config::NetworkCondition {
+                    interface: vec!["tun0".into(), "wg*".into()],
+                    ..Default::default()
+                }),
+                fallback,
+                ..Default::default()
+            }],
+        )
+    }
+
+    #[test]
+    fn network_condition_met_uses_pattern_browsers() {
+        let cfg = vpn_cfg(vec![]);
+        let opener = FakeOpener::new();
+        let notifier = FakeNotifier::new();
+        let ctx = RouteContext {
+            network: Box::new(FakeNetwork(vec!["eth0", "wg0"])),
+            ..ctx()
+        };
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &NoopSelector,
+            &ctx,
+            "https://intranet.example.com/wiki",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["Work"]);
+        assert!(notifier.skipped.borrow().is_empty());
+    }
+
+    #[test]
+    fn network_condition_unmet_uses_fallback() {
+        let cfg = vpn_cfg(vec!["Other".into()]);
+        let opener = FakeOpener::new();
+        let notifier = FakeNotifier::new();
+        let mut trace = Trace::default();
+        let res = open_url_traced(
+            &cfg,
+            &opener,
+            &notifier,
+            &NoopSelector,
+            &ctx(),
+            "https://intranet.example.com/wiki",
+            false,
+            &mut trace,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["Other"]);
+        assert!(notifier.skipped.borrow().is_empty());
+        assert!(trace.fallback);
+    }
+
+    #[test]
+    fn network_condition_unmet_without_fallback_notifies_and_continues() {
+        let cfg = vpn_cfg(vec![]);
+        let opener = FakeOpener::new();
+        let notifier = FakeNotifier::new();
+        let res = open_url_with(
+            &cfg,
+            &opener,
+            &notifier,
+            &NoopSelector,
+            &ctx(),
+            "https://intranet.example.com/wiki",
+            false,
+        );
+        assert!(res.is_ok());
+        assert_eq!(opener.opens.borrow().as_slice(), ["Personal"]);
+        let skipped = notifier.skipped.borrow();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].1, "intranet.example.com");
+        assert_eq!(skipped[0].2, "interface tun0/wg* is not up");
+    }
+
+    #[test]
+    fn workspace_condition_routes_to_client_profile() {
+        let cfg = cfg_with(
+            vec![browser("Default"), browser("ClientA")],
+            vec![PatternEntry {
+                pattern: "*".into(),
+                browsers: vec!["ClientA".into()],
+                workspace: vec!["client-A".into()],
+                ..Default::default()
+            }],
+        );
+        let notifier = FakeNotifier::new();
+        for (workspace, expected) in [(Some("client-A"), "ClientA"), (Some("misc"), "Default")] {
+            let opener = FakeOpener::new();
+            let ctx = RouteContext {
+                desktop: Box::new(FakeDesktop(workspace)),
+                ..ctx()
+            };
+            let res = open_url_with(
+                &cfg,
+                &opener,
+                &notifier,
+                &NoopSelector,
+                &ctx,
+                "https://example.com",
+                false,
+            );
+            assert!(res.is_ok());
+            assert_eq!(opener.opens.borrow().as_slice(), [expected]);
+        }
+        assert!(notifier.skipped.borrow().is_empty());
+        assert_eq!(
+            notifier.opened.borrow().as_slice(),
+            [
+                (Some("*".to_string()), "ClientA".to_string()),
+                (None, "Default".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_records_dialog_pattern_and_browser() {
+        let cfg = cfg_with(
+            vec![browser("A"), browser("B")],
+            vec![PatternEntry {
+                pattern: "example.com".into(),
+                browsers: vec!["A".into(), "B".into()],
+                ..Default::default()
+            }],
+        );
+        let opener = FakeOpener::new();
+        opener.queue_outcomes("A", vec![Err(anyhow!("fail A"))]);
+        opener.queue_outcomes("B", vec![Ok(())]);
+        let notifier = FakeNotifier::new();
+        let mut trace = Trace::default();
+        let res = open_url_traced(
+            &cfg,
+            &opener,
+            &notifier,
+            &SelectIdx(0),
+            &ctx(),
+            "https://example.com",
+            false,
+            &mut trace,
+        );
+        assert!(res.is_ok());
+        assert_eq!(
+            trace,
+            Trace {
+                pattern: Some("example.com".into()),
+                browser: Some("B".into()),
+                dialog: true,
+                fallback: false,
+            }
+        );
+
+        // The selector reporting an error means no dialog was shown
+        let mut trace = Trace::default();
+        let res = open_url_traced(
+            &cfg,
+            &opener,
+            &notifier,
+            &ErrorSelector,
+            &ctx(),
+            "https://example.com",
+            false,
+            &mut trace,
+        );
+        assert!(res.is_ok());
+        assert_eq!(
+            trace,
+            Trace {
+                pattern: Some("example.com".into()),
+                browser: Some("A".into()),
+                dialog: false,
+                fallback: false,
+            }
+        );
+    }
+
+    #[test]
+    fn history_error_follows_history_redaction() {
+        let mut cfg = cfg_with(vec![browser("A")], vec![]);
+        cfg.notifications.redact_urls = false;
+        cfg.history.redact_urls = true;
+        let opener = FakeOpener::new();
+        opener.queue_outcomes("A", vec![Err(anyhow!("not found"))]);
+        let mut trace = Trace::default();
+        let res = open_url_traced(
+            &cfg,
+            &opener,
+            &FakeNotifier::new(),
+            &NoopSelector,
+            &ctx(),
+            "https://example.com/private?token=1",
+            false,
+            &mut trace,
+        );
+        let entry = crate::history::Entry::new(
+            "https://example.com/private?token=1",
+            &cfg.history,
+            trace,
+            &res,
+        );
+        let line = serde_json::to_string(&entry).unwrap();
+        assert!(!line.contains("private"), "{line}");
+        assert_eq!(
+            entry.error.as_deref(),
+            Some("Failed to open with default browser 'A': not found")
+        );
+    }
 }
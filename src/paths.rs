@@ -1,4 +1,8 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Configuration file given with `--config`; takes precedence over `MUXIE_CONFIG`.
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
 
 #[cfg(feature = "self-install")]
 pub(crate) fn dbus_service_dir() -> PathBuf {
@@ -15,14 +19,29 @@ pub(crate) fn dbus_service_path() -> PathBuf {
     p
 }
 
+#[cfg(feature = "self-install")]
+pub(crate) fn systemd_user_unit_path() -> PathBuf {
+    let mut p = dirs::data_dir().expect("Failed to get user data directory");
+    p.push("systemd");
+    p.push("user");
+    p.push(crate::systemd::SYSTEMD_UNIT);
+    p
+}
+
 #[cfg(feature = "self-install")]
 pub fn desktop_entry_path() -> PathBuf {
     let mut path = dirs::data_dir().expect("Failed to get user data directory");
     path.push("applications");
-    path.push("muxie.desktop");
+    path.push(format!("{}.desktop", crate::daemon::DBUS_SERVICE));
     path
 }
 
+/// Desktop entry written by versions before the entry was named after the D-Bus service.
+#[cfg(feature = "self-install")]
+pub fn legacy_desktop_entry_path() -> PathBuf {
+    desktop_entry_path().with_file_name("muxie.desktop")
+}
+
 #[cfg(feature = "self-install")]
 pub fn icon_path(size: &str, name: &str) -> PathBuf {
     {
@@ -50,13 +69,101 @@ pub fn icon_paths() -> Vec<PathBuf> {
     }
 }
 
+/// Use `path` instead of the default configuration file for the rest of the process.
+pub(crate) fn set_config_path(path: &Path) {
+    let _ = CONFIG_PATH.set(std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()));
+}
+
+/// The configuration file: `--config`, else `MUXIE_CONFIG`, else the default file.
 pub fn config_path() -> PathBuf {
+    if let Some(path) = CONFIG_PATH.get() {
+        return path.clone();
+    }
+    match std::env::var_os("MUXIE_CONFIG").filter(|v| !v.is_empty()) {
+        Some(path) => std::path::absolute(&path).unwrap_or_else(|_| PathBuf::from(path)),
+        None => default_config_path(),
+    }
+}
+
+fn default_config_path() -> PathBuf {
     let mut config_dir = dirs::config_dir().expect("Failed to get user config directory");
     config_dir.push("muxie");
     config_dir.push("muxie.toml");
     config_dir
 }
 
+/// System-wide configuration files, most important first: `muxie/muxie.toml` in each
+/// directory of `XDG_CONFIG_DIRS` (default `/etc/xdg`). Relative entries are ignored, as
+/// the XDG specification requires. The files need not exist.
+pub(crate) fn system_config_paths() -> Vec<PathBuf> {
+    let dirs = std::env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    let mut out: Vec<PathBuf> = Vec::new();
+    for dir in dirs.split(':').map(PathBuf::from) {
+        let path = dir.join("muxie").join("muxie.toml");
+        if dir.is_absolute() && !out.contains(&path) {
+            out.push(path);
+        }
+    }
+    out
+}
+
+/// YAML configuration file used before 0.2.0.
+pub(crate) fn legacy_config_path() -> PathBuf {
+    let mut p = dirs::config_dir().expect("Failed to get user config directory");
+    p.push("muxie.toml");
+    p
+}
+
+/// Log file written with `[logging] file = true`.
+pub(crate) fn log_file_path() -> PathBuf {
+    let mut p = dirs::state_dir().expect("Failed to get user state directory");
+    p.push("muxie");
+    p.push("muxie.log");
+    p
+}
+
+/// Unix socket of the daemon, or `None` if `XDG_RUNTIME_DIR` is not set.
+pub(crate) fn socket_path() -> Option<PathBuf> {
+    let name = match instance() {
+        Some(instance) => format!("muxie-{instance}.sock"),
+        None => "muxie.sock".to_string(),
+    };
+    dirs::runtime_dir().map(|dir| dir.join(name))
+}
+
+/// Name of the muxie instance using a configuration file other than the default one,
+/// so its daemon gets its own bus name and socket. `None` for the default file.
+pub(crate) fn instance() -> Option<String> {
+    let path = config_path();
+    (path != default_config_path()).then(|| instance_name(&path))
+}
+
+/// The file stem and a hash of the whole path, valid as an element of a D-Bus name.
+fn instance_name(path: &Path) -> String {
+    let mut stem: String = path
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !stem.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        stem.insert(0, '_');
+    }
+    // FNV-1a, so clients and daemons of any build agree on the name
+    let hash = path
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0x811c_9dc5_u32, |h, b| {
+            (h ^ u32::from(*b)).wrapping_mul(0x0100_0193)
+        });
+    format!("{stem}_{hash:08x}")
+}
+
 #[cfg(feature = "self-install")]
 pub fn state_path() -> PathBuf {
     let mut p = dirs::state_dir().expect("Failed to get user state directory");
@@ -64,3 +171,33 @@ pub fn state_path() -> PathBuf {
     p.push("state.toml");
     p
 }
+
+/// Open history written with `[history] enabled = true`.
+pub(crate) fn history_path() -> PathBuf {
+    let mut p = dirs::state_dir().expect("Failed to get user state directory");
+    p.push("muxie");
+    p.push("history.jsonl");
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_names_are_valid_bus_name_elements() {
+        let name = instance_name(Path::new("/home/u/candidate.toml"));
+        assert!(name.starts_with("candidate_"));
+        assert_eq!(name.len(), "candidate_".len() + 8);
+        assert_eq!(name, instance_name(Path::new("/home/u/candidate.toml")));
+        assert_ne!(name, instance_name(Path::new("/tmp/candidate.toml")));
+        for path in ["/tmp/2-work.toml", "/tmp/my config.toml", "/tmp/.toml"] {
+            let name = instance_name(Path::new(path));
+            let bus = format!("{}.{name}", crate::daemon::DBUS_SERVICE);
+            assert!(
+                zbus_names::WellKnownName::try_from(bus.as_str()).is_ok(),
+                "{bus}"
+            );
+        }
+    }
+}
@@ -22,6 +22,34 @@ impl Pattern for String {
     }
 }
 
+/// Whether every URL matched by `later` is also matched by `earlier`, so that `later` can
+/// never be reached after `earlier`. Returns `false` when this cannot be decided cheaply.
+pub(crate) fn subsumes(earlier: &str, later: &str) -> bool {
+    if earlier == later || (!earlier.is_empty() && earlier.chars().all(|c| c == '*')) {
+        return true;
+    }
+    if earlier.is_empty() || earlier.contains('*') {
+        return false;
+    }
+    // A substring pattern matches any URL that contains one of `later`'s literal parts
+    later.split('*').any(|part| part.contains(earlier))
+}
+
+#[test]
+fn test_subsumes() {
+    assert!(subsumes("*", "example.com"));
+    assert!(subsumes("example.com", "example.com"));
+    assert!(subsumes("example.com", "docs.example.com"));
+    assert!(subsumes("example.com", "https://*.example.com/*"));
+    assert!(!subsumes("docs.example.com", "example.com"));
+    assert!(!subsumes("https://*.example.com/*", "example.com"));
+    assert!(!subsumes(
+        "https://*.example.com/*",
+        "https://*.example.com/docs/*"
+    ));
+    assert!(!subsumes("", "example.com"));
+}
+
 #[test]
 fn test_matching() {
     assert!(
@@ -0,0 +1,338 @@
+use crate::browser::Browser;
+use std::path::{Path, PathBuf};
+
+/// Lookup of running processes, used to prefer browsers that are already open.
+pub(crate) trait ProcessProbe {
+    fn is_running(&self, browser: &Browser) -> bool;
+}
+
+/// The application that asked to open a URL.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SourceApp {
+    pub pid: u32,
+    /// Basename of the executable (`/proc/<pid>/exe`), falling back to `comm`.
+    pub exe: Option<String>,
+    /// Desktop application ID or Flatpak app ID derived from the process cgroup.
+    pub app_id: Option<String>,
+}
+
+impl SourceApp {
+    /// Names a `source` matcher is checked against.
+    pub(crate) fn identifiers(&self) -> impl Iterator<Item = &str> {
+        self.exe
+            .iter()
+            .chain(self.app_id.iter())
+            .map(|s| s.as_str())
+    }
+
+    /// Short human-readable description for logs.
+    pub(crate) fn describe(&self) -> String {
+        let ids: Vec<&str> = self.identifiers().collect();
+        if ids.is_empty() {
+            format!("pid {}", self.pid)
+        } else {
+            format!("{} (pid {})", ids.join(", "), self.pid)
+        }
+    }
+}
+
+/// Processes that only relay a URL on behalf of the real source application.
+const LAUNCHERS: &[&str] = &[
+    "muxie",
+    "xdg-open",
+    "gio",
+    "gio-launch-desktop",
+    "gvfs-open",
+    "kde-open",
+    "kde-open5",
+    "kioclient",
+    "kioclient5",
+    "exo-open",
+    "env",
+    "sh",
+    "bash",
+    "dash",
+    "zsh",
+];
+
+/// Probe backed by procfs. Matching is best effort:
+/// - Flatpak browsers (`flatpak run <app-id>`) match on the `app-flatpak-<app-id>-*` cgroup.
+/// - Other browsers match when the executable name equals the process `comm`,
+///   the basename of `argv[0]`, or the basename of `/proc/<pid>/exe`.
+pub(crate) struct ProcProbe {
+    root: PathBuf,
+}
+
+impl Default for ProcProbe {
+    fn default() -> Self {
+        ProcProbe {
+            root: PathBuf::from("/proc"),
+        }
+    }
+}
+
+impl ProcProbe {
+    #[cfg(test)]
+    pub(crate) fn with_root(root: impl Into<PathBuf>) -> Self {
+        ProcProbe { root: root.into() }
+    }
+
+    /// Identify the application that opened a URL, starting from `pid` and walking up
+    /// the process tree past launchers such as `xdg-open` or `muxie` itself.
+    pub(crate) fn source_app(&self, pid: u32) -> Option<SourceApp> {
+        let mut pid = pid;
+        // Guard against cycles or very deep trees
+        for _ in 0..16 {
+            if pid <= 1 {
+                return None;
+            }
+            let dir = self.root.join(pid.to_string());
+            let comm = std::fs::read_to_string(dir.join("comm")).ok()?;
+            let comm = comm.trim_end().to_string();
+            let exe = std::fs::read_link(dir.join("exe"))
+                .ok()
+                .and_then(|p| p.file_name().and_then(|n| n.to_str()).map(String::from));
+            let name = exe.clone().unwrap_or_else(|| comm.clone());
+            if !LAUNCHERS.contains(&name.as_str()) && !LAUNCHERS.contains(&comm.as_str()) {
+                let app_id = std::fs::read_to_string(dir.join("cgroup"))
+                    .ok()
+                    .and_then(|cg| app_id_from_cgroup(&cg));
+                return Some(SourceApp {
+                    pid,
+                    exe: Some(name),
+                    app_id,
+                });
+            }
+            pid = parent_pid(&dir)?;
+        }
+        None
+    }
+
+    fn pids(&self) -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                let name = e.file_name();
+                let name = name.to_string_lossy();
+                !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit())
+            })
+            .map(|e| e.path())
+            .collect()
+    }
+}
+
+impl ProcessProbe for ProcProbe {
+    fn is_running(&self, browser: &Browser) -> bool {
+        if let Some(app_id) = flatpak_app_id(browser) {
+            let marker = format!("app-flatpak-{app_id}-");
+            return self.pids().iter().any(|pid| {
+                std::fs::read_to_string(pid.join("cgroup"))
+                    .map(|cg| cg.contains(&marker))
+                    .unwrap_or(false)
+            });
+        }
+        let Some(target) = basename(&browser.executable) else {
+            return false;
+        };
+        // The kernel truncates `comm` to 15 bytes
+        let comm_target: String = target.chars().take(15).collect();
+        self.pids()
+            .iter()
+            .any(|pid| process_matches(pid, target, &comm_target))
+    }
+}
+
+fn process_matches(pid: &Path, target: &str, comm_target: &str) -> bool {
+    if let Ok(comm) = std::fs::read_to_string(pid.join("comm"))
+        && comm.trim_end() == comm_target
+    {
+        return true;
+    }
+    if let Ok(cmdline) = std::fs::read(pid.join("cmdline")) {
+        let argv0 = cmdline.split(|b| *b == 0).next().unwrap_or_default();
+        if basename(&String::from_utf8_lossy(argv0)) == Some(target) {
+            return true;
+        }
+    }
+    if let Ok(exe) = std::fs::read_link(pid.join("exe"))
+        && exe.file_name().and_then(|n| n.to_str()) == Some(target)
+    {
+        return true;
+    }
+    false
+}
+
+fn parent_pid(dir: &Path) -> Option<u32> {
+    // Format: `pid (comm) state ppid ...`; comm may contain spaces and parentheses
+    let stat = std::fs::read_to_string(dir.join("stat")).ok()?;
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Derive an application ID from a systemd cgroup path, following the
+/// `app[-<launcher>]-<app-id>[-<random>].scope` / `app[-<launcher>]-<app-id>[@<random>].service`
+/// naming used by desktop environments and Flatpak.
+fn app_id_from_cgroup(cgroup: &str) -> Option<String> {
+    let unit = cgroup
+        .lines()
+        .filter_map(|l| l.rsplit('/').next())
+        .find(|seg| {
+            seg.starts_with("app-") && (seg.ends_with(".scope") || seg.ends_with(".service"))
+        })?;
+    let (stem, is_scope) = match unit.strip_suffix(".scope") {
+        Some(stem) => (stem, true),
+        None => (unit.strip_suffix(".service")?, false),
+    };
+    let stem = stem.strip_prefix("app-")?;
+    let stem = if is_scope {
+        // Trailing random part of scopes is numeric or hex
+        match stem.rsplit_once('-') {
+            Some((head, tail)) if tail.chars().all(|c| c.is_ascii_hexdigit()) => head,
+            _ => stem,
+        }
+    } else {
+        stem.split('@').next().unwrap_or(stem)
+    };
+    let stem = ["flatpak-", "gnome-", "kde-", "xfce-", "dbus-", "systemd-"]
+        .iter()
+        .find_map(|p| stem.strip_prefix(p))
+        .unwrap_or(stem);
+    let id = stem.replace("\\x2d", "-");
+    if id.is_empty() { None } else { Some(id) }
+}
+
+fn basename(s: &str) -> Option<&str> {
+    let s = s.trim();
+    let name = s.rsplit('/').next().unwrap_or(s);
+    if name.is_empty() { None } else { Some(name) }
+}
+
+/// Extract the Flatpak application ID from `flatpak run [options] <app-id>`.
+pub(crate) fn flatpak_app_id(browser: &Browser) -> Option<&str> {
+    if basename(&browser.executable) != Some("flatpak") {
+        return None;
+    }
+    let mut args = browser.args.iter().map(|s| s.as_str());
+    args.find(|a| *a == "run")?;
+    args.find(|a| !a.starts_with('-') && !a.starts_with('%'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    fn browser(executable: &str, args: &[&str]) -> Browser {
+        Browser {
+            name: "B".into(),
+            executable: executable.into(),
+            args: args.iter().map(|s| s.to_string()).collect(),
+            locked: false,
+        }
+    }
+
+    fn fake_proc(tag: &str) -> TempDir {
+        TempDir::new(&format!("proc-{tag}"))
+    }
+
+    fn add_process(root: &Path, pid: u32, comm: &str, argv0: &str, cgroup: &str) {
+        let dir = root.join(pid.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("comm"), format!("{comm}\n")).unwrap();
+        std::fs::write(dir.join("cmdline"), format!("{argv0}\0--flag\0")).unwrap();
+        std::fs::write(dir.join("cgroup"), cgroup).unwrap();
+    }
+
+    #[test]
+    fn app_id_from_cgroup_variants() {
+        assert_eq!(
+            app_id_from_cgroup(
+                "0::/user.slice/user-1000.slice/user@1000.service/app.slice/app-flatpak-com.slack.Slack-4242.scope\n"
+            ),
+            Some("com.slack.Slack".to_string())
+        );
+        assert_eq!(
+            app_id_from_cgroup(
+                "0::/user.slice/app.slice/app-gnome-org.mozilla.Thunderbird-1234.scope\n"
+            ),
+            Some("org.mozilla.Thunderbird".to_string())
+        );
+        assert_eq!(
+            app_id_from_cgroup("0::/user.slice/app.slice/app-org.kde.konsole@0a1b2c.service\n"),
+            Some("org.kde.konsole".to_string())
+        );
+        assert_eq!(app_id_from_cgroup("0::/user.slice/session-2.scope\n"), None);
+    }
+
+    #[test]
+    fn source_app_skips_launchers() {
+        let root = fake_proc("source");
+        let app = root.join("50");
+        std::fs::create_dir_all(&app).unwrap();
+        std::fs::write(app.join("comm"), "slack\n").unwrap();
+        std::fs::write(app.join("stat"), "50 (slack) S 1 50 50 0").unwrap();
+        std::fs::write(
+            app.join("cgroup"),
+            "0::/app.slice/app-flatpak-com.slack.Slack-99.scope\n",
+        )
+        .unwrap();
+        let opener = root.join("60");
+        std::fs::create_dir_all(&opener).unwrap();
+        std::fs::write(opener.join("comm"), "xdg-open\n").unwrap();
+        std::fs::write(opener.join("stat"), "60 (xdg-open) S 50 60 60 0").unwrap();
+        let muxie = root.join("70");
+        std::fs::create_dir_all(&muxie).unwrap();
+        std::fs::write(muxie.join("comm"), "muxie\n").unwrap();
+        std::fs::write(muxie.join("stat"), "70 (muxie) R 60 70 70 0").unwrap();
+
+        let probe = ProcProbe::with_root(root.to_path_buf());
+        let src = probe.source_app(70).unwrap();
+        assert_eq!(src.pid, 50);
+        assert_eq!(src.exe.as_deref(), Some("slack"));
+        assert_eq!(src.app_id.as_deref(), Some("com.slack.Slack"));
+        assert!(probe.source_app(1).is_none());
+    }
+
+    #[test]
+    fn flatpak_app_id_parsing() {
+        assert_eq!(
+            flatpak_app_id(&browser(
+                "/usr/bin/flatpak",
+                &["run", "--branch=stable", "org.mozilla.firefox", "%u"]
+            )),
+            Some("org.mozilla.firefox")
+        );
+        assert_eq!(flatpak_app_id(&browser("firefox", &["%u"])), None);
+        assert_eq!(flatpak_app_id(&browser("flatpak", &["list"])), None);
+    }
+
+    #[test]
+    fn detects_running_by_comm_and_argv0() {
+        let root = fake_proc("comm");
+        add_process(&root, 100, "firefox", "/usr/lib/firefox/firefox", "0::/\n");
+        add_process(&root, 200, "bash", "/usr/bin/bash", "0::/\n");
+        std::fs::create_dir_all(root.join("self")).unwrap();
+        let probe = ProcProbe::with_root(root.to_path_buf());
+        assert!(probe.is_running(&browser("firefox", &[])));
+        assert!(probe.is_running(&browser("/usr/bin/bash", &[])));
+        assert!(!probe.is_running(&browser("chromium", &[])));
+    }
+
+    #[test]
+    fn detects_running_flatpak_by_cgroup() {
+        let root = fake_proc("flatpak");
+        add_process(
+            &root,
+            300,
+            "bwrap",
+            "bwrap",
+            "0::/user.slice/app-flatpak-org.chromium.Chromium-1234.scope\n",
+        );
+        let probe = ProcProbe::with_root(root.to_path_buf());
+        assert!(probe.is_running(&browser("flatpak", &["run", "org.chromium.Chromium"])));
+        assert!(!probe.is_running(&browser("flatpak", &["run", "org.mozilla.firefox"])));
+    }
+}
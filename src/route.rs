@@ -0,0 +1,386 @@
+use crate::browser::Browser;
+use crate::condition::{Mismatch, describe_when, evaluate};
+use crate::config::{Config, PatternEntry, read_config};
+use crate::logging::ROUTING;
+use crate::open::RouteContext;
+use anyhow::Result;
+use std::io::{self, Write};
+
+/// Browsers a pattern would try, in order. The first `offered` of them are shown in the
+/// selection dialog when there are at least two.
+#[derive(Debug, Clone)]
+pub(crate) struct Candidates<'a> {
+    pub browsers: Vec<&'a Browser>,
+    pub offered: usize,
+}
+
+impl Candidates<'_> {
+    pub(crate) fn dialog(&self) -> bool {
+        self.offered >= 2
+    }
+
+    fn names(&self) -> String {
+        let names: Vec<&str> = self.browsers.iter().map(|b| b.name.as_str()).collect();
+        names.join(", ")
+    }
+}
+
+/// How a single pattern entry was evaluated for a URL.
+#[derive(Debug)]
+pub(crate) enum Outcome<'a> {
+    /// Pattern lists no browsers and is ignored.
+    Ignored,
+    Skipped(Mismatch),
+    /// Pattern matched but none of its browsers are configured.
+    NoKnownBrowsers,
+    Matched(Candidates<'a>),
+    /// URL matched but a condition failed; the pattern's fallback browsers apply.
+    Fallback(Mismatch, Candidates<'a>),
+}
+
+#[derive(Debug)]
+pub(crate) struct PatternReport<'a> {
+    pub index: usize,
+    pub entry: &'a PatternEntry,
+    /// Included file that defines the pattern; `None` for the main configuration file.
+    pub file: Option<&'a std::path::Path>,
+    pub outcome: Outcome<'a>,
+}
+
+/// Dry-run explanation of how a URL would be routed.
+#[derive(Debug)]
+pub(crate) struct RouteReport<'a> {
+    pub patterns: Vec<PatternReport<'a>>,
+    pub default: Option<&'a Browser>,
+}
+
+impl<'a> RouteReport<'a> {
+    /// The first applicable pattern (if any) and the browsers it would try, in order.
+    /// Falls back to the default browser when no pattern applies.
+    pub(crate) fn selected(&self) -> Option<(Option<&'a PatternEntry>, Candidates<'a>)> {
+        for p in &self.patterns {
+            if let Outcome::Matched(candidates) | Outcome::Fallback(_, candidates) = &p.outcome {
+                return Some((Some(p.entry), candidates.clone()));
+            }
+        }
+        self.default.map(|b| {
+            let candidates = Candidates {
+                browsers: vec![b],
+                offered: 1,
+            };
+            (None, candidates)
+        })
+    }
+
+    /// Print the report. URL mismatches are only listed with `verbose >= 1`.
+    pub(crate) fn print_to<W: Write>(&self, mut w: W, verbose: u8) {
+        for p in &self.patterns {
+            let when = p
+                .entry
+                .when
+                .as_ref()
+                .map(|w| format!(" (when {})", describe_when(w)))
+                .unwrap_or_default();
+            let file = p
+                .file
+                .map(|f| format!(" in {}", f.display()))
+                .unwrap_or_default();
+            let head = format!(
+                "patterns[{}] '{}'{}{}",
+                p.index, p.entry.pattern, when, file
+            );
+            match &p.outcome {
+                Outcome::Skipped(Mismatch::Url) | Outcome::Ignored if verbose == 0 => {}
+                Outcome::Ignored => {
+                    let _ = writeln!(w, "- {head}: ignored (no browsers listed)");
+                }
+                Outcome::Skipped(reason) => {
+                    let _ = writeln!(w, "- {head}: skipped, {reason}");
+                }
+                Outcome::NoKnownBrowsers => {
+                    let _ = writeln!(w, "- {head}: matched, but no listed browser is configured");
+                }
+                Outcome::Matched(candidates) => {
+                    let _ = writeln!(w, "- {head}: matched → {}", candidates.names());
+                }
+                Outcome::Fallback(reason, candidates) => {
+                    let _ = writeln!(w, "- {head}: {reason}, fallback → {}", candidates.names());
+                }
+            }
+        }
+        match self.selected() {
+            Some((Some(entry), candidates)) => {
+                let prompt = if candidates.dialog() {
+                    " (selection dialog)"
+                } else {
+                    ""
+                };
+                let _ = writeln!(
+                    w,
+                    "Result: pattern '{}' → {}{}",
+                    entry.pattern,
+                    candidates.names(),
+                    prompt
+                );
+            }
+            Some((None, candidates)) => {
+                let _ = writeln!(
+                    w,
+                    "Result: no pattern applies → default browser '{}'",
+                    candidates.browsers[0].name
+                );
+            }
+            None => {
+                let _ = writeln!(w, "Result: no browsers configured");
+            }
+        }
+    }
+}
+
+/// Evaluate every pattern entry for `url` without opening anything.
+pub(crate) fn explain<'a>(config: &'a Config, ctx: &RouteContext, url: &str) -> RouteReport<'a> {
+    RouteReport {
+        patterns: (0..config.patterns.len())
+            .map(|index| assess(config, index, ctx, url))
+            .collect(),
+        default: config.browsers.first(),
+    }
+}
+
+/// Evaluate `config.patterns[index]` for `url`: whether it applies and the browsers it
+/// would try, in order. Opening a URL and `muxie route` both decide through this.
+pub(crate) fn assess<'a>(
+    config: &'a Config,
+    index: usize,
+    ctx: &RouteContext,
+    url: &str,
+) -> PatternReport<'a> {
+    let entry = &config.patterns[index];
+    let outcome = if entry.browsers.is_empty() {
+        Outcome::Ignored
+    } else {
+        match evaluate(entry, ctx, url) {
+            Err(reason) if reason.is_condition() && !entry.fallback.is_empty() => {
+                match candidates(config, entry, &entry.fallback, ctx) {
+                    Some(candidates) => Outcome::Fallback(reason, candidates),
+                    None => Outcome::NoKnownBrowsers,
+                }
+            }
+            Err(reason) => Outcome::Skipped(reason),
+            Ok(()) => match candidates(config, entry, &entry.browsers, ctx) {
+                Some(candidates) => Outcome::Matched(candidates),
+                None => Outcome::NoKnownBrowsers,
+            },
+        }
+    };
+    PatternReport {
+        index,
+        entry,
+        file: config.provenance.pattern_file(index),
+        outcome,
+    }
+}
+
+/// The configured browsers among `names`, or `None` if there are none. With
+/// `prefer_running`, browsers that are already running go first and the dialog only
+/// offers those.
+fn candidates<'a>(
+    config: &'a Config,
+    entry: &PatternEntry,
+    names: &[String],
+    ctx: &RouteContext,
+) -> Option<Candidates<'a>> {
+    let mut browsers: Vec<&Browser> = Vec::new();
+    for name in names {
+        match config.browsers.iter().find(|b| &b.name == name) {
+            Some(b) => browsers.push(b),
+            None => log::debug!(target: ROUTING, "Skipping unknown browser '{name}' in pattern"),
+        }
+    }
+    if browsers.is_empty() {
+        return None;
+    }
+    let mut offered = browsers.len();
+    if entry.prefer_running && browsers.len() >= 2 {
+        let (running, idle): (Vec<&Browser>, Vec<&Browser>) =
+            browsers.iter().partition(|b| ctx.processes.is_running(b));
+        if !running.is_empty() {
+            let names: Vec<&str> = running.iter().map(|b| b.name.as_str()).collect();
+            log::debug!(target: ROUTING, "Already running: {}", names.join(", "));
+            offered = running.len();
+            browsers = running.into_iter().chain(idle).collect();
+        }
+    }
+    Some(Candidates { browsers, offered })
+}
+
+/// `muxie route <url>`: show how a URL would be routed right now.
+pub(crate) fn route(url: &str, verbose: u8) -> Result<()> {
+    let cfg = read_config()?;
+    let ctx = RouteContext::system();
+    println!("Local time: {}", ctx.now.format("%a %Y-%m-%d %H:%M"));
+    explain(&cfg, &ctx, url).print_to(io::stdout(), verbose);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::When;
+
+    fn browser(name: &str) -> Browser {
+        Browser {
+            name: name.to_string(),
+            executable: name.to_lowercase(),
+            args: vec![],
+            locked: false,
+        }
+    }
+
+    struct NoNetwork;
+
+    impl crate::network::NetworkProbe for NoNetwork {
+        fn interfaces_up(&self) -> Vec<String> {
+            Vec::new()
+        }
+        fn default_route_interfaces(&self) -> Vec<String> {
+            Vec::new()
+        }
+        fn wifi_ssids(&self) -> Vec<String> {
+            Vec::new()
+        }
+    }
+
+    struct NoDesktop;
+
+    impl crate::desktop::DesktopProbe for NoDesktop {
+        fn env_var(&self, _name: &str) -> Option<String> {
+            None
+        }
+        fn active_workspace(&self) -> Option<String> {
+            None
+        }
+    }
+
+    fn ctx_at(day: u32, hour: u32) -> RouteContext {
+        RouteContext {
+            network: Box::new(NoNetwork),
+            desktop: Box::new(NoDesktop),
+            now: chrono::NaiveDate::from_ymd_opt(2025, 9, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap(),
+            ..RouteContext::system()
+        }
+    }
+
+    fn cfg() -> Config {
+        Config {
+            version: 1,
+            browsers: vec![browser("Personal"), browser("Work")],
+            patterns: vec![
+                PatternEntry {
+                    pattern: "intranet".into(),
+                    browsers: vec!["Work".into()],
+                    ..Default::default()
+                },
+                PatternEntry {
+                    pattern: "*".into(),
+                    browsers: vec!["Work".into()],
+                    when: Some(When {
+                        days: vec!["mon-fri".into()],
+                        hours: Some("09:00-18:00".into()),
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn explains_schedule_skip_and_default() {
+        let cfg = cfg();
+        // 2025-09-06 is a Saturday
+        let report = explain(&cfg, &ctx_at(6, 12), "https://example.com");
+        assert!(matches!(
+            report.patterns[1].outcome,
+            Outcome::Skipped(Mismatch::Schedule)
+        ));
+        let (entry, candidates) = report.selected().unwrap();
+        assert!(entry.is_none());
+        assert_eq!(candidates.browsers[0].name, "Personal");
+
+        let mut buf: Vec<u8> = Vec::new();
+        report.print_to(&mut buf, 0);
+        let s = String::from_utf8(buf).unwrap();
+        assert!(!s.contains("'intranet'"));
+        assert!(s.contains(
+            "- patterns[1] '*' (when mon-fri 09:00-18:00): skipped, outside of 'when' schedule\n"
+        ));
+        assert!(s.ends_with("Result: no pattern applies → default browser 'Personal'\n"));
+    }
+
+    #[test]
+    fn selects_first_match_during_schedule() {
+        let cfg = cfg();
+        let report = explain(&cfg, &ctx_at(1, 10), "https://example.com");
+        let (entry, candidates) = report.selected().unwrap();
+        assert_eq!(entry.unwrap().pattern, "*");
+        assert_eq!(candidates.browsers[0].name, "Work");
+    }
+
+    #[test]
+    fn explains_network_fallback() {
+        let mut cfg = cfg();
+        cfg.patterns[0].network = Some(crate::config::NetworkCondition {
+            interface: vec!["tun0".into()],
+            ..Default::default()
+        });
+        cfg.patterns[0].fallback = vec!["Personal".into()];
+        let report = explain(&cfg, &ctx_at(6, 12), "https://intranet.example.com");
+        let (entry, candidates) = report.selected().unwrap();
+        assert_eq!(entry.unwrap().pattern, "intranet");
+        assert_eq!(candidates.browsers[0].name, "Personal");
+
+        let mut buf: Vec<u8> = Vec::new();
+        report.print_to(&mut buf, 0);
+        let s = String::from_utf8(buf).unwrap();
+        assert!(s.contains(
+            "- patterns[0] 'intranet': network condition not met: interface tun0 is not up, fallback → Personal\n"
+        ));
+    }
+
+    struct Running(&'static str);
+
+    impl crate::process::ProcessProbe for Running {
+        fn is_running(&self, browser: &Browser) -> bool {
+            browser.name == self.0
+        }
+    }
+
+    #[test]
+    fn running_browser_goes_first_without_dialog() {
+        let mut cfg = cfg();
+        cfg.patterns[0].browsers = vec!["Personal".into(), "Work".into()];
+        cfg.patterns[0].prefer_running = true;
+        let ctx = RouteContext {
+            processes: Box::new(Running("Work")),
+            ..ctx_at(6, 12)
+        };
+        let report = explain(&cfg, &ctx, "https://intranet.example.com");
+        let (_, candidates) = report.selected().unwrap();
+        let names: Vec<&str> = candidates
+            .browsers
+            .iter()
+            .map(|b| b.name.as_str())
+            .collect();
+        assert_eq!(names, ["Work", "Personal"]);
+        assert!(!candidates.dialog());
+
+        let mut buf: Vec<u8> = Vec::new();
+        report.print_to(&mut buf, 0);
+        let s = String::from_utf8(buf).unwrap();
+        assert!(s.ends_with("Result: pattern 'intranet' → Work, Personal\n"));
+    }
+}
@@ -0,0 +1,271 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Longest accepted request line, including the URL.
+const MAX_REQUEST: u64 = 64 * 1024;
+
+pub(crate) const ERROR_CANCELED: &str = "canceled";
+pub(crate) const ERROR_FAILED: &str = "failed";
+pub(crate) const ERROR_INVALID: &str = "invalid";
+
+/// One request per line, e.g. `{"cmd":"open","url":"https://example.com"}`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub(crate) enum Request {
+    Open { url: String },
+    Route { url: String },
+    Reload,
+    Status,
+}
+
+/// One response line per request. On failure `error` is one of `canceled`, `failed` or
+/// `invalid` and `message` describes the problem.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<RouteInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+impl Response {
+    pub(crate) fn success() -> Self {
+        Response {
+            ok: true,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn error(code: &str, message: impl Into<String>) -> Self {
+        Response {
+            ok: false,
+            error: Some(code.to_string()),
+            message: Some(message.into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Result of a `route` request; `pattern` is `None` for the default browser.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RouteInfo {
+    pub browser: String,
+    pub pattern: Option<String>,
+    pub argv: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Status {
+    pub pid: u32,
+    pub config_path: String,
+    pub config_version: u32,
+    pub pattern_count: usize,
+    /// Unix time of the last successful configuration load.
+    pub last_reload: u64,
+    /// Whether the daemon is also reachable over D-Bus.
+    pub dbus: bool,
+}
+
+/// Answers requests received on the socket.
+pub(crate) trait Handler: Send + Sync {
+    /// `peer_pid` is the process ID of the client, if known.
+    fn handle(&self, req: Request, peer_pid: Option<u32>) -> Response;
+}
+
+/// Listening socket; the socket file is removed when this is dropped.
+pub(crate) struct SocketServer {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl SocketServer {
+    /// Bind to `path`, replacing a stale socket file left behind by a crashed daemon.
+    pub(crate) fn bind(path: &Path) -> Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                anyhow::bail!("Another daemon is listening on {}", path.display());
+            }
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind {}", path.display()))?;
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        Ok(SocketServer {
+            path: path.to_path_buf(),
+            listener,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accept connections on a background thread. Each connection is served on its own
+    /// thread since an `open` request may wait for a selection dialog.
+    pub(crate) fn spawn(&self, handler: Arc<dyn Handler>) -> Result<()> {
+        let listener = self.listener.try_clone()?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let handler = handler.clone();
+                std::thread::spawn(move || serve_connection(stream, handler.as_ref()));
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Drop for SocketServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn serve_connection(stream: UnixStream, handler: &dyn Handler) {
+    let peer_pid = peer_pid(&stream);
+    let Ok(read_half) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(read_half);
+    let mut writer = stream;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match (&mut reader).take(MAX_REQUEST).read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        if !line.ends_with('\n') && line.len() as u64 >= MAX_REQUEST {
+            let _ = write_line(
+                &mut writer,
+                &Response::error(ERROR_INVALID, "request too large"),
+            );
+            return;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let resp = match serde_json::from_str::<Request>(line.trim()) {
+            Ok(req) => handler.handle(req, peer_pid),
+            Err(e) => Response::error(ERROR_INVALID, e.to_string()),
+        };
+        if write_line(&mut writer, &resp).is_err() {
+            return;
+        }
+    }
+}
+
+/// Write `msg` as a single JSON line.
+pub(crate) fn write_line<T: Serialize, W: Write>(w: &mut W, msg: &T) -> std::io::Result<()> {
+    let mut buf = serde_json::to_vec(msg)?;
+    buf.push(b'\n');
+    w.write_all(&buf)?;
+    w.flush()
+}
+
+fn peer_pid(stream: &UnixStream) -> Option<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes and sized for SO_PEERCRED
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (rc == 0 && cred.pid > 0).then_some(cred.pid as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    #[test]
+    fn protocol_wire_format() {
+        let req: Request = serde_json::from_str(r#"{"cmd":"open","url":"https://x"}"#).unwrap();
+        assert_eq!(
+            req,
+            Request::Open {
+                url: "https://x".into()
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&Request::Status).unwrap(),
+            r#"{"cmd":"status"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Response::success()).unwrap(),
+            r#"{"ok":true}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Response::error(ERROR_CANCELED, "no")).unwrap(),
+            r#"{"ok":false,"error":"canceled","message":"no"}"#
+        );
+    }
+
+    struct Echo;
+
+    impl Handler for Echo {
+        fn handle(&self, req: Request, peer_pid: Option<u32>) -> Response {
+            match req {
+                Request::Open { url } => Response::error(ERROR_FAILED, url),
+                _ => Response {
+                    message: peer_pid.map(|p| p.to_string()),
+                    ..Response::success()
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn serves_requests_and_replaces_stale_socket() {
+        let dir = TempDir::new("sock");
+        let path = dir.join("muxie.sock");
+        // A socket file nobody listens on
+        drop(UnixListener::bind(&path).unwrap());
+
+        let server = SocketServer::bind(&path).unwrap();
+        server.spawn(Arc::new(Echo)).unwrap();
+        assert!(SocketServer::bind(&path).is_err());
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut roundtrip = |line: &str| -> Response {
+            writer.write_all(line.as_bytes()).unwrap();
+            let mut out = String::new();
+            reader.read_line(&mut out).unwrap();
+            serde_json::from_str(&out).unwrap()
+        };
+        let resp = roundtrip("{\"cmd\":\"status\"}\n");
+        assert!(resp.ok);
+        assert_eq!(resp.message, Some(std::process::id().to_string()));
+        let resp = roundtrip("{\"cmd\":\"open\",\"url\":\"https://x\"}\n");
+        assert_eq!(resp.message.as_deref(), Some("https://x"));
+        let resp = roundtrip("not json\n");
+        assert_eq!(resp.error.as_deref(), Some(ERROR_INVALID));
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
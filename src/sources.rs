@@ -0,0 +1,619 @@
+use crate::browser::Browser;
+use crate::config::{Config, PatternEntry, ValidationError};
+use crate::migrate;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Drop-in directory next to the main configuration file.
+pub(crate) const CONF_D: &str = "conf.d";
+
+/// Where a browser or pattern entry was defined. `file` is `None` for the main
+/// configuration file; `index` is the position within that file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub file: Option<PathBuf>,
+    pub index: usize,
+}
+
+/// A user browser definition that was dropped because a system-wide file locks the name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overridden {
+    pub name: String,
+    pub origin: Origin,
+    pub locked_by: PathBuf,
+}
+
+/// Origins of `Config::browsers` and `Config::patterns`, by position. Empty for
+/// configurations that were not loaded from disk.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Provenance {
+    pub browsers: Vec<Origin>,
+    pub patterns: Vec<Origin>,
+    pub overridden: Vec<Overridden>,
+}
+
+impl Provenance {
+    /// File that defines `config.patterns[index]`, if it is not the main file.
+    pub(crate) fn pattern_file(&self, index: usize) -> Option<&Path> {
+        self.patterns.get(index)?.file.as_deref()
+    }
+
+    /// Rewrite a `browsers[N]…`/`patterns[N]…` path to the position within the file that
+    /// defines the entry, and name that file unless it is the main one. Entries of the
+    /// main file can follow locked system-wide ones, so their paths need rewriting too.
+    pub(crate) fn attribute(&self, issue: &mut ValidationError) {
+        let Some(path) = &issue.path else { return };
+        for (kind, origins) in [("browsers", &self.browsers), ("patterns", &self.patterns)] {
+            let Some((index, rest)) = path
+                .strip_prefix(kind)
+                .and_then(|p| p.strip_prefix('['))
+                .and_then(|p| p.split_once(']'))
+            else {
+                continue;
+            };
+            if let Ok(index) = index.parse::<usize>()
+                && let Some(origin) = origins.get(index)
+            {
+                issue.path = Some(format!("{kind}[{}]{rest}", origin.index));
+                issue.file = origin.file.as_ref().map(|f| f.display().to_string());
+            }
+            return;
+        }
+    }
+}
+
+/// Error parsing one of the configuration files.
+#[derive(Debug)]
+pub(crate) struct ParseError {
+    pub file: PathBuf,
+    pub error: toml::de::Error,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to parse config file (TOML): {}",
+            self.file.display()
+        )
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Contents allowed in included files.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fragment {
+    #[serde(default)]
+    browsers: Vec<Browser>,
+    #[serde(default)]
+    patterns: Vec<PatternEntry>,
+}
+
+/// Just the `include` list of the main file, read even when the rest of it is invalid.
+#[derive(Debug, Default, Deserialize)]
+struct Includes {
+    #[serde(default)]
+    include: Vec<String>,
+}
+
+fn read(file: &Path) -> Result<String> {
+    std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read config file: {}", file.display()))
+}
+
+fn parse<T: serde::de::DeserializeOwned>(file: &Path) -> Result<T> {
+    from_str(file, &read(file)?)
+}
+
+fn from_str<T: serde::de::DeserializeOwned>(file: &Path, text: &str) -> Result<T> {
+    toml::from_str(text).map_err(|error| {
+        ParseError {
+            file: file.to_path_buf(),
+            error,
+        }
+        .into()
+    })
+}
+
+/// Parse the `text` of a main configuration file, upgrading an older schema version in
+/// memory.
+fn parse_config(file: &Path, text: &str) -> Result<Config> {
+    let table: toml::Table = from_str(file, text)?;
+    let in_file = |e: anyhow::Error| anyhow::anyhow!("{}: {e}", file.display());
+    let version = migrate::version(&table).map_err(in_file)?;
+    if version == migrate::CURRENT_VERSION {
+        return from_str(file, text);
+    }
+    let doc = migrate::upgrade(text, version).map_err(in_file)?;
+    log::warn!(
+        "{} uses configuration version {version}; run `muxie config migrate --write` to update it",
+        file.display()
+    );
+    toml::from_str(&doc.to_string())
+        .with_context(|| format!("Invalid configuration after migration: {}", file.display()))
+}
+
+/// Read `main` and merge the browsers and patterns of included files after its own:
+/// first the `include` entries in order, then `conf.d/*.toml` sorted by name.
+pub(crate) fn load(main: &Path) -> Result<Config> {
+    load_text(main, &read(main)?)
+}
+
+/// `load` with `text` as the contents of `main`.
+fn load_text(main: &Path, text: &str) -> Result<Config> {
+    let mut config = parse_config(main, text)?;
+    let origin = |file: Option<&Path>, index| Origin {
+        file: file.map(Path::to_path_buf),
+        index,
+    };
+    config.provenance = Provenance {
+        browsers: (0..config.browsers.len())
+            .map(|i| origin(None, i))
+            .collect(),
+        patterns: (0..config.patterns.len())
+            .map(|i| origin(None, i))
+            .collect(),
+        overridden: Vec::new(),
+    };
+    for file in included_files(main, &config.include)? {
+        let fragment: Fragment = parse(&file)?;
+        for (i, browser) in fragment.browsers.into_iter().enumerate() {
+            config.provenance.browsers.push(origin(Some(&file), i));
+            config.browsers.push(browser);
+        }
+        for (i, pattern) in fragment.patterns.into_iter().enumerate() {
+            config.provenance.patterns.push(origin(Some(&file), i));
+            config.patterns.push(pattern);
+        }
+    }
+    Ok(config)
+}
+
+/// Merge `over` into `base`, recursing into tables present in both.
+fn merge_tables(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge_tables(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Entries of a system-wide file paired with their origins, which name the file even
+/// for its own entries.
+fn system_entries<T>(items: Vec<T>, origins: &[Origin], file: &Path) -> Vec<(T, Origin)> {
+    items
+        .into_iter()
+        .zip(origins.iter().map(|o| Origin {
+            file: Some(o.file.clone().unwrap_or_else(|| file.to_path_buf())),
+            index: o.index,
+        }))
+        .collect()
+}
+
+/// Read `main` on top of the system-wide files in `system`, most important first (the
+/// order of `XDG_CONFIG_DIRS`); missing files are skipped. Settings are merged key by
+/// key with `main` winning. A user browser replaces a system one of the same name
+/// unless that one is `locked`. Locked system patterns are tried before the user's
+/// patterns and the other system patterns after them.
+pub(crate) fn load_layered(main: &Path, system: &[PathBuf]) -> Result<Config> {
+    let text = main.is_file().then(|| read(main)).transpose()?;
+    layered(main, text.as_deref(), system)
+}
+
+/// `load_layered` with `text` as the contents of `main`, e.g. to check an edit before
+/// writing it.
+pub(crate) fn load_layered_text(main: &Path, text: &str, system: &[PathBuf]) -> Result<Config> {
+    layered(main, Some(text), system)
+}
+
+fn layered(main: &Path, text: Option<&str>, system: &[PathBuf]) -> Result<Config> {
+    let system: Vec<&Path> = system
+        .iter()
+        .map(PathBuf::as_path)
+        .filter(|f| *f != main && f.is_file())
+        .collect();
+    if system.is_empty() {
+        return match text {
+            Some(text) => load_text(main, text),
+            None => load(main),
+        };
+    }
+    let user = text.map(|text| load_text(main, text)).transpose()?;
+
+    let mut settings = toml::Table::new();
+    for file in system.iter().rev() {
+        merge_tables(&mut settings, parse(file)?);
+    }
+    if let Some(text) = text {
+        merge_tables(&mut settings, from_str(main, text)?);
+    }
+    for key in ["include", "browsers", "patterns"] {
+        settings.remove(key);
+    }
+    let mut config: Config = toml::Value::Table(settings)
+        .try_into()
+        .context("Invalid configuration after merging system-wide files")?;
+
+    let mut browsers: Vec<(Browser, Origin)> = Vec::new();
+    let (mut locked, mut unlocked) = (Vec::new(), Vec::new());
+    for file in system {
+        let layer = load(file)?;
+        for (browser, origin) in system_entries(layer.browsers, &layer.provenance.browsers, file) {
+            // The most important file defines each name
+            if !browsers.iter().any(|(b, _)| b.name == browser.name) {
+                browsers.push((browser, origin));
+            }
+        }
+        for entry in system_entries(layer.patterns, &layer.provenance.patterns, file) {
+            if entry.0.locked {
+                locked.push(entry);
+            } else {
+                unlocked.push(entry);
+            }
+        }
+    }
+
+    let mut patterns = locked;
+    if let Some(user) = user {
+        for (browser, origin) in user.browsers.into_iter().zip(user.provenance.browsers) {
+            match browsers.iter().position(|(b, _)| b.name == browser.name) {
+                Some(i) if browsers[i].0.locked => config.provenance.overridden.push(Overridden {
+                    name: browser.name,
+                    origin,
+                    locked_by: browsers[i].1.file.clone().unwrap_or_default(),
+                }),
+                Some(i) => browsers[i] = (browser, origin),
+                None => browsers.push((browser, origin)),
+            }
+        }
+        patterns.extend(user.patterns.into_iter().zip(user.provenance.patterns));
+    }
+    patterns.extend(unlocked);
+
+    (config.browsers, config.provenance.browsers) = browsers.into_iter().unzip();
+    (config.patterns, config.provenance.patterns) = patterns.into_iter().unzip();
+    Ok(config)
+}
+
+/// The configuration as TOML, each browser and pattern preceded by a comment naming the
+/// file it came from. `include` is left out since the included entries are listed.
+pub(crate) fn render(config: &Config, main: &Path) -> Result<String> {
+    let mut effective = config.clone();
+    effective.include.clear();
+    let mut doc: toml_edit::DocumentMut = toml::to_string_pretty(&effective)?.parse()?;
+    for (key, origins) in [
+        ("browsers", &config.provenance.browsers),
+        ("patterns", &config.provenance.patterns),
+    ] {
+        let Some(tables) = doc.get_mut(key).and_then(|i| i.as_array_of_tables_mut()) else {
+            continue;
+        };
+        for (table, origin) in tables.iter_mut().zip(origins) {
+            let file = origin.file.as_deref().unwrap_or(main);
+            table
+                .decor_mut()
+                .set_prefix(format!("\n# {}\n", file.display()));
+        }
+    }
+    Ok(doc.to_string())
+}
+
+/// `muxie config show`: print the effective configuration after merging system-wide
+/// files, includes and `conf.d`.
+pub(crate) fn show() -> Result<()> {
+    let config = crate::config::read_config()?;
+    print!("{}", render(&config, &crate::paths::config_path())?);
+    Ok(())
+}
+
+fn base_dir(main: &Path) -> PathBuf {
+    main.parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Resolve an `include` entry: `~/` is the home directory and relative paths are
+/// relative to the main file's directory.
+fn resolve(spec: &str, base: &Path) -> PathBuf {
+    match spec.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => base.join(spec),
+    }
+}
+
+/// Split a path whose file name contains `*` into its directory and name pattern.
+fn split_glob(path: &Path) -> Result<Option<(PathBuf, String)>> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    if dir.to_string_lossy().contains('*') {
+        bail!(
+            "Wildcards are only supported in the file name of includes: {}",
+            path.display()
+        );
+    }
+    Ok(name
+        .contains('*')
+        .then(|| (dir.to_path_buf(), name.replace('?', r"\?"))))
+}
+
+fn glob_matches(pattern: &str, name: &std::ffi::OsStr) -> bool {
+    wildflower::Pattern::new(pattern).matches(&name.to_string_lossy())
+}
+
+/// Files in `dir` whose name matches `pattern`, sorted. A missing directory has none.
+fn list_dir(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to list {}", dir.display())),
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file() && glob_matches(pattern, &e.file_name()))
+        .map(|e| e.path())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Included files in merge order, without duplicates and without the main file itself.
+fn included_files(main: &Path, include: &[String]) -> Result<Vec<PathBuf>> {
+    let base = base_dir(main);
+    let mut out: Vec<PathBuf> = Vec::new();
+    let mut push = |file: PathBuf| {
+        if file != main && !out.contains(&file) {
+            out.push(file);
+        }
+    };
+    for spec in include {
+        let path = resolve(spec, &base);
+        match split_glob(&path)? {
+            Some((dir, pattern)) => list_dir(&dir, &pattern)?.into_iter().for_each(&mut push),
+            None if path.is_file() => push(path),
+            None => bail!("Included file not found: {}", path.display()),
+        }
+    }
+    list_dir(&base.join(CONF_D), "*.toml")?
+        .into_iter()
+        .for_each(push);
+    Ok(out)
+}
+
+/// Files and directories whose changes affect the configuration.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct WatchSet {
+    files: BTreeSet<PathBuf>,
+    /// Directory and file name pattern of wildcard includes and `conf.d`.
+    globs: Vec<(PathBuf, String)>,
+}
+
+impl WatchSet {
+    /// What to watch for `main` and the system-wide files layered under it. Uses only
+    /// the `include` lists, so a file that fails to parse is still watched for the fix.
+    pub(crate) fn for_config(main: &Path, system: &[PathBuf]) -> Self {
+        let mut set = WatchSet::default();
+        for file in std::iter::once(main).chain(system.iter().map(PathBuf::as_path)) {
+            set.add(file);
+        }
+        set
+    }
+
+    fn add(&mut self, main: &Path) {
+        let base = base_dir(main);
+        let includes: Includes = parse(main).unwrap_or_default();
+        self.files.insert(main.to_path_buf());
+        for spec in &includes.include {
+            let path = resolve(spec, &base);
+            match split_glob(&path) {
+                Ok(Some(glob)) => self.globs.push(glob),
+                Ok(None) => {
+                    self.files.insert(path);
+                }
+                Err(_) => {}
+            }
+        }
+        self.globs.push((base.join(CONF_D), "*.toml".to_string()));
+    }
+
+    /// Directories to watch non-recursively: the parents of all files, wildcard
+    /// directories and their parents (to notice the directory being created).
+    pub(crate) fn dirs(&self) -> BTreeSet<PathBuf> {
+        let mut dirs = BTreeSet::new();
+        for file in &self.files {
+            dirs.extend(file.parent().map(Path::to_path_buf));
+        }
+        for (dir, _) in &self.globs {
+            dirs.insert(dir.clone());
+            dirs.extend(dir.parent().map(Path::to_path_buf));
+        }
+        dirs.retain(|d| d.is_dir());
+        dirs
+    }
+
+    pub(crate) fn is_relevant(&self, path: &Path) -> bool {
+        if self.files.contains(path) {
+            return true;
+        }
+        self.globs.iter().any(|(dir, pattern)| {
+            path == dir
+                || (path.parent() == Some(dir.as_path())
+                    && path.file_name().is_some_and(|n| glob_matches(pattern, n)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TempDir;
+
+    fn write(path: &Path, text: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn browser(name: &str) -> String {
+        format!("[[browsers]]\nname = \"{name}\"\nexecutable = \"{name}\"\n")
+    }
+
+    #[test]
+    fn merges_includes_and_conf_d_in_order() {
+        let dir = TempDir::new("sources");
+        let main = dir.join("muxie.toml");
+        write(
+            &main,
+            &format!(
+                "include = [\"work.toml\", \"extra/*.toml\"]\n{}[[patterns]]\npattern = \"a\"\nbrowsers = [\"Main\"]\n",
+                browser("Main")
+            ),
+        );
+        write(
+            &dir.join("work.toml"),
+            "[[patterns]]\npattern = \"work\"\nbrowsers = [\"Nope\"]\n",
+        );
+        write(&dir.join("extra/b.toml"), &browser("B"));
+        write(&dir.join("extra/a.toml"), &browser("A"));
+        write(&dir.join("extra/skip.txt"), "not toml");
+        write(&dir.join("conf.d/10-late.toml"), &browser("Late"));
+
+        let config = load(&main).unwrap();
+        let names: Vec<&str> = config.browsers.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["Main", "A", "B", "Late"]);
+        assert_eq!(config.patterns.len(), 2);
+        assert_eq!(config.provenance.pattern_file(0), None);
+        assert_eq!(
+            config.provenance.pattern_file(1),
+            Some(dir.join("work.toml").as_path())
+        );
+
+        // Errors in included files name the file and the position within it
+        let result = config.validate(true);
+        let unknown = result
+            .issues
+            .iter()
+            .find(|e| e.code == "pattern.browser.unknown")
+            .unwrap();
+        assert_eq!(unknown.path.as_deref(), Some("patterns[0].browsers[0]"));
+        assert_eq!(
+            unknown.file.as_deref(),
+            Some(dir.join("work.toml").to_str().unwrap())
+        );
+
+        let set = WatchSet::for_config(&main, &[]);
+        assert!(set.is_relevant(&dir.join("work.toml")));
+        assert!(set.is_relevant(&dir.join("extra/new.toml")));
+        assert!(set.is_relevant(&dir.join("conf.d")));
+        assert!(set.is_relevant(&dir.join("conf.d/20-new.toml")));
+        assert!(!set.is_relevant(&dir.join("extra/skip.txt")));
+        assert!(!set.is_relevant(&dir.join("other.toml")));
+        assert_eq!(
+            set.dirs(),
+            BTreeSet::from([dir.to_path_buf(), dir.join("extra"), dir.join("conf.d")])
+        );
+
+        // Included files may only define browsers and patterns
+        write(&dir.join("conf.d/30-bad.toml"), "[daemon]\nsocket = true\n");
+        let err = load(&main).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ParseError>().unwrap().file,
+            dir.join("conf.d/30-bad.toml")
+        );
+        write(&main, "include = [\"missing.toml\"]\n");
+        assert!(load(&main).is_err());
+    }
+
+    #[test]
+    fn layers_system_files_under_the_user_file() {
+        let dir = TempDir::new("layers");
+        let main = dir.join("home/muxie.toml");
+        let corp = dir.join("corp/muxie/muxie.toml");
+        let vendor = dir.join("vendor/muxie/muxie.toml");
+        write(
+            &corp,
+            "[dialog]\nprovider = \"zenity\"\n\n[[browsers]]\nname = \"Managed\"\nexecutable = \"managed\"\nlocked = true\n\n[[patterns]]\npattern = \"sso.corp\"\nbrowsers = [\"Managed\"]\nlocked = true\n\n[[patterns]]\npattern = \"wiki.corp\"\nbrowsers = [\"Managed\"]\n",
+        );
+        write(
+            &vendor,
+            &format!(
+                "[notifications]\nenabled = false\nredact_urls = false\n{}{}",
+                browser("Managed"),
+                browser("Shared")
+            ),
+        );
+        write(
+            &main,
+            &format!(
+                "[notifications]\nredact_urls = true\n{}{}[[patterns]]\npattern = \"corp\"\nbrowsers = [\"Shared\"]\n",
+                browser("Managed"),
+                browser("Shared")
+            ),
+        );
+        let system = [corp.clone(), dir.join("missing/muxie.toml"), vendor.clone()];
+
+        let config = load_layered(&main, &system).unwrap();
+        // Settings merge key by key, the user's file last
+        assert!(!config.notifications.enabled);
+        assert!(config.notifications.redact_urls);
+        assert_eq!(
+            config.dialog.provider,
+            crate::config::DialogProvider::Zenity
+        );
+
+        // The locked browser stays; the unlocked one is replaced by the user's
+        let browsers: Vec<(&str, &str)> = config
+            .browsers
+            .iter()
+            .map(|b| (b.name.as_str(), b.executable.as_str()))
+            .collect();
+        assert_eq!(browsers, [("Managed", "managed"), ("Shared", "Shared")]);
+        assert_eq!(config.provenance.browsers[0].file.as_ref(), Some(&corp));
+        assert_eq!(config.provenance.browsers[1].file, None);
+
+        let patterns: Vec<&str> = config.patterns.iter().map(|p| p.pattern.as_str()).collect();
+        assert_eq!(patterns, ["sso.corp", "corp", "wiki.corp"]);
+        assert_eq!(config.provenance.pattern_file(2), Some(corp.as_path()));
+
+        let result = config.validate(false);
+        let locked = result
+            .issues
+            .iter()
+            .find(|e| e.code == "browser.locked")
+            .unwrap();
+        assert_eq!(locked.path.as_deref(), Some("browsers[0]"));
+        assert_eq!(locked.file, None);
+
+        let shown = render(&config, &main).unwrap();
+        assert!(shown.contains(&format!(
+            "# {}\n[[patterns]]\npattern = \"sso.corp\"",
+            corp.display()
+        )));
+        assert!(shown.contains(&format!(
+            "# {}\n[[browsers]]\nname = \"Shared\"",
+            main.display()
+        )));
+
+        // System files alone are enough
+        std::fs::remove_file(&main).unwrap();
+        let config = load_layered(&main, &system).unwrap();
+        assert_eq!(config.browsers.len(), 2);
+        assert!(config.provenance.overridden.is_empty());
+
+        let set = WatchSet::for_config(&main, &system);
+        assert!(set.is_relevant(&vendor));
+        assert!(set.is_relevant(&dir.join("corp/muxie/conf.d/extra.toml")));
+    }
+}
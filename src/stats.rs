@@ -0,0 +1,311 @@
+use crate::config::{Config, read_config};
+use crate::history::{Entry, Outcome};
+use crate::pattern::Pattern;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Counts for one pattern entry, or for the default browser.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub(crate) struct Counts {
+    pub matched: usize,
+    pub opened: usize,
+    pub failed: usize,
+    pub canceled: usize,
+    /// Opens that used the pattern's `fallback` browsers.
+    pub fallback: usize,
+    pub dialog: usize,
+    /// Browser name to number of opens (or last attempts, for failures).
+    pub browsers: BTreeMap<String, usize>,
+    /// Unix time of the most recent match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_matched: Option<i64>,
+}
+
+impl Counts {
+    fn add(&mut self, entry: &Entry) {
+        self.matched += 1;
+        match entry.outcome {
+            Outcome::Opened => self.opened += 1,
+            Outcome::Failed => self.failed += 1,
+            Outcome::Canceled => self.canceled += 1,
+        }
+        self.fallback += usize::from(entry.fallback);
+        self.dialog += usize::from(entry.dialog);
+        if let Some(browser) = &entry.browser {
+            *self.browsers.entry(browser.clone()).or_default() += 1;
+        }
+        self.last_matched = self.last_matched.max(Some(entry.time));
+    }
+}
+
+/// An earlier pattern that took URLs a never-matching pattern would also match.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Shadow {
+    pub index: usize,
+    pub pattern: String,
+    pub urls: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct PatternStats {
+    /// Position in `config.patterns`.
+    pub index: usize,
+    pub pattern: String,
+    #[serde(flatten)]
+    pub counts: Counts,
+    /// Only filled in for patterns that never matched.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shadowed_by: Vec<Shadow>,
+}
+
+/// Routing statistics over the history entries recorded since `since`.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Report {
+    pub since: Option<i64>,
+    pub entries: usize,
+    pub patterns: Vec<PatternStats>,
+    pub default: Counts,
+    /// Entries attributed to patterns that are no longer in the configuration.
+    pub removed: BTreeMap<String, usize>,
+}
+
+impl Report {
+    pub(crate) fn build(config: &Config, entries: &[Entry], since: Option<i64>) -> Self {
+        let mut patterns: Vec<PatternStats> = config
+            .patterns
+            .iter()
+            .enumerate()
+            .map(|(index, p)| PatternStats {
+                index,
+                pattern: p.pattern.clone(),
+                counts: Counts::default(),
+                shadowed_by: Vec::new(),
+            })
+            .collect();
+        // History records the pattern text; duplicates are attributed to the first entry
+        let index_of = |pattern: &str| config.patterns.iter().position(|p| p.pattern == pattern);
+
+        let recent: Vec<&Entry> = entries
+            .iter()
+            .filter(|e| since.is_none_or(|since| e.time >= since))
+            .collect();
+        let mut default = Counts::default();
+        let mut removed = BTreeMap::new();
+        for entry in &recent {
+            match entry.pattern.as_deref() {
+                None => default.add(entry),
+                Some(pattern) => match index_of(pattern) {
+                    Some(i) => patterns[i].counts.add(entry),
+                    None => *removed.entry(pattern.to_string()).or_default() += 1,
+                },
+            }
+        }
+
+        // A pattern that never matched although an earlier pattern took URLs it matches is
+        // shadowed by that pattern. Redacted URLs only contain the host, so patterns that
+        // need a path or scheme cannot be checked against them.
+        for (i, stats) in patterns.iter_mut().enumerate() {
+            if stats.counts.matched > 0 || config.patterns[i].browsers.is_empty() {
+                continue;
+            }
+            let mut by: BTreeMap<usize, usize> = BTreeMap::new();
+            for entry in &recent {
+                if let Some(j) = entry.pattern.as_deref().and_then(index_of)
+                    && j < i
+                    && config.patterns[i].pattern.matches(&entry.url)
+                {
+                    *by.entry(j).or_default() += 1;
+                }
+            }
+            stats.shadowed_by = by
+                .into_iter()
+                .map(|(j, urls)| Shadow {
+                    index: j,
+                    pattern: config.patterns[j].pattern.clone(),
+                    urls,
+                })
+                .collect();
+        }
+
+        Report {
+            since,
+            entries: recent.len(),
+            patterns,
+            default,
+            removed,
+        }
+    }
+
+    pub(crate) fn print_to<W: Write>(&self, mut w: W) {
+        let _ = writeln!(
+            w,
+            "{} recorded open(s){}",
+            self.entries,
+            match self.since {
+                Some(since) => format!(" since {}", format_time(since)),
+                None => String::new(),
+            }
+        );
+        let mut dead = Vec::new();
+        for p in &self.patterns {
+            if p.counts.matched == 0 {
+                dead.push(p);
+                continue;
+            }
+            let _ = writeln!(w, "patterns[{}] '{}':", p.index, p.pattern);
+            print_counts(&mut w, &p.counts);
+        }
+        if self.default.matched > 0 {
+            let _ = writeln!(w, "default browser:");
+            print_counts(&mut w, &self.default);
+        }
+        for (pattern, count) in &self.removed {
+            let _ = writeln!(w, "'{pattern}' (no longer configured): {count} match(es)");
+        }
+        if !dead.is_empty() {
+            let _ = writeln!(w, "Never matched:");
+            for p in dead {
+                let _ = writeln!(w, "  patterns[{}] '{}'", p.index, p.pattern);
+                for s in &p.shadowed_by {
+                    let _ = writeln!(
+                        w,
+                        "    shadowed by patterns[{}] '{}' ({} URL(s))",
+                        s.index, s.pattern, s.urls
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn print_counts<W: Write>(w: &mut W, c: &Counts) {
+    let mut line = format!(
+        "  {} match(es): {} opened, {} failed, {} canceled",
+        c.matched, c.opened, c.failed, c.canceled
+    );
+    if c.fallback > 0 {
+        line.push_str(&format!(", {} via fallback", c.fallback));
+    }
+    if c.dialog > 0 {
+        line.push_str(&format!(", {} with dialog", c.dialog));
+    }
+    if let Some(last) = c.last_matched {
+        line.push_str(&format!("; last {}", format_time(last)));
+    }
+    let _ = writeln!(w, "{line}");
+    if !c.browsers.is_empty() {
+        let browsers: Vec<String> = c
+            .browsers
+            .iter()
+            .map(|(name, n)| format!("{name} ({n})"))
+            .collect();
+        let _ = writeln!(w, "  browsers: {}", browsers.join(", "));
+    }
+}
+
+fn format_time(time: i64) -> String {
+    use chrono::TimeZone;
+    chrono::Local
+        .timestamp_opt(time, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| time.to_string())
+}
+
+/// `muxie stats`: summarize the open history against the current patterns. `days` limits
+/// the window; `0` uses all recorded history.
+pub(crate) fn stats(days: u32, json: bool) -> Result<()> {
+    let config = read_config()?;
+    let entries = crate::history::read(&crate::paths::history_path())?;
+    if entries.is_empty() && !config.history.enabled {
+        eprintln!("No history recorded. Enable it with `enabled = true` in the [history] section.");
+        return Ok(());
+    }
+    let since = (days > 0).then(|| chrono::Local::now().timestamp() - i64::from(days) * 24 * 3600);
+    let report = Report::build(&config, &entries, since);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        report.print_to(std::io::stdout());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PatternEntry;
+
+    fn pattern(p: &str) -> PatternEntry {
+        PatternEntry {
+            pattern: p.into(),
+            browsers: vec!["A".into()],
+            ..Default::default()
+        }
+    }
+
+    fn entry(time: i64, url: &str, pattern: Option<&str>, outcome: Outcome) -> Entry {
+        Entry {
+            time,
+            url: url.into(),
+            pattern: pattern.map(str::to_string),
+            browser: Some("A".into()),
+            dialog: false,
+            fallback: false,
+            outcome,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn counts_matches_and_finds_shadowed_patterns() {
+        let config = Config {
+            patterns: vec![
+                pattern("example"),
+                pattern("docs.example.com"),
+                pattern("unused.org"),
+            ],
+            ..Default::default()
+        };
+        let entries = vec![
+            // Outside the window
+            entry(50, "docs.example.com", Some("unused.org"), Outcome::Opened),
+            entry(100, "docs.example.com", Some("example"), Outcome::Opened),
+            entry(110, "www.example.com", Some("example"), Outcome::Failed),
+            Entry {
+                fallback: true,
+                dialog: true,
+                ..entry(120, "x.org", Some("gone"), Outcome::Opened)
+            },
+            entry(130, "x.org", None, Outcome::Canceled),
+        ];
+        let report = Report::build(&config, &entries, Some(100));
+        assert_eq!(report.entries, 4);
+
+        let first = &report.patterns[0].counts;
+        assert_eq!((first.matched, first.opened, first.failed), (2, 1, 1));
+        assert_eq!(first.browsers, BTreeMap::from([("A".to_string(), 2)]));
+        assert_eq!(first.last_matched, Some(110));
+        assert_eq!(
+            report.patterns[1].shadowed_by,
+            vec![Shadow {
+                index: 0,
+                pattern: "example".into(),
+                urls: 1
+            }]
+        );
+        assert_eq!(report.patterns[2].counts.matched, 0);
+        assert!(report.patterns[2].shadowed_by.is_empty());
+        assert_eq!(report.default.canceled, 1);
+        assert_eq!(report.removed, BTreeMap::from([("gone".to_string(), 1)]));
+
+        let mut out = Vec::new();
+        report.print_to(&mut out);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            "Never matched:\n  patterns[1] 'docs.example.com'\n    shadowed by patterns[0] 'example' (1 URL(s))\n  patterns[2] 'unused.org'\n"
+        ));
+    }
+}
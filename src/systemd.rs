@@ -0,0 +1,80 @@
+use anyhow::{Context, Result, bail};
+
+/// Name of the systemd user unit that runs the daemon.
+pub const SYSTEMD_UNIT: &str = "muxie.service";
+
+/// Contents of the systemd user unit for the daemon binary at `exec`.
+#[cfg(any(feature = "self-install", test))]
+pub(crate) fn unit_contents(exec: &str) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Muxie browser router\n\
+         Documentation=https://github.com/adfxyz/muxie/\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         Type=dbus\n\
+         BusName={}\n\
+         ExecStart={exec} daemon run\n\
+         Restart=on-failure\n\
+         RestartSec=2\n\
+         # Browsers started by the daemon share its cgroup; keep them running when it stops\n\
+         KillMode=process\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        crate::daemon::DBUS_SERVICE
+    )
+}
+
+/// Contents of the D-Bus activation file. `SystemdService` hands activation to systemd
+/// when the bus supports it; `Exec` is used otherwise.
+#[cfg(any(feature = "self-install", test))]
+pub(crate) fn dbus_service_contents(exec: &str) -> String {
+    format!(
+        "[D-BUS Service]\nName={}\nExec={exec} daemon run\nSystemdService={SYSTEMD_UNIT}\n",
+        crate::daemon::DBUS_SERVICE
+    )
+}
+
+/// `muxie daemon enable`: start the daemon with the graphical session.
+pub fn enable() -> Result<()> {
+    systemctl(&["enable", "--now", SYSTEMD_UNIT])
+}
+
+/// `muxie daemon disable`: stop the daemon and no longer start it with the session.
+/// D-Bus activation still starts it on demand.
+pub fn disable() -> Result<()> {
+    systemctl(&["disable", "--now", SYSTEMD_UNIT])
+}
+
+/// Run `systemctl --user` with `args`.
+pub(crate) fn systemctl(args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .context("Failed to run systemctl (is systemd available?)")?;
+    if !status.success() {
+        bail!("systemctl --user {} failed with {status}", args.join(" "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packaged_files_match_generated() {
+        assert_eq!(
+            unit_contents("/usr/bin/muxie"),
+            include_str!("../resources/packaging/shared/muxie.service")
+        );
+        assert_eq!(
+            dbus_service_contents("/usr/bin/muxie"),
+            include_str!("../resources/packaging/shared/xyz.adf.Muxie.service")
+        );
+    }
+}
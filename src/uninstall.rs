@@ -1,4 +1,7 @@
-use crate::paths::{config_path, dbus_service_path, desktop_entry_path, icon_paths, state_path};
+use crate::paths::{
+    config_path, dbus_service_path, desktop_entry_path, icon_paths, legacy_desktop_entry_path,
+    state_path, systemd_user_unit_path,
+};
 use crate::state::{read_state, remove_state_file};
 use anyhow::Result;
 use std::fs;
@@ -74,7 +77,9 @@ pub fn uninstall(yes: bool, dry_run: bool, restore_default: bool) -> Result<()>
     }
 
     let desktop = desktop_entry_path();
+    let legacy_desktop = legacy_desktop_entry_path();
     let dbus_service = dbus_service_path();
+    let systemd_unit = systemd_user_unit_path();
     let icons = icon_paths();
     let cfg = config_path();
     let state = state_path();
@@ -82,6 +87,10 @@ pub fn uninstall(yes: bool, dry_run: bool, restore_default: bool) -> Result<()>
     println!("Planned actions:");
     println!("- Remove desktop entry: {}", desktop.display());
     println!("- Remove D-Bus service: {}", dbus_service.display());
+    println!(
+        "- Stop and remove systemd user unit: {}",
+        systemd_unit.display()
+    );
     for p in &icons {
         println!("- Remove icon: {}", p.display());
     }
@@ -110,11 +119,13 @@ pub fn uninstall(yes: bool, dry_run: bool, restore_default: bool) -> Result<()>
     let mut failures: Vec<(PathBuf, String)> = Vec::new();
     let mut removed: Vec<PathBuf> = Vec::new();
 
-    // Remove desktop entry
-    if desktop.exists() {
-        match fs::remove_file(&desktop) {
-            Ok(_) => removed.push(desktop.clone()),
-            Err(e) => failures.push((desktop.clone(), e.to_string())),
+    // Remove desktop entry (and the one written by older versions)
+    for entry in [&desktop, &legacy_desktop] {
+        if entry.exists() {
+            match fs::remove_file(entry) {
+                Ok(_) => removed.push(entry.clone()),
+                Err(e) => failures.push((entry.clone(), e.to_string())),
+            }
         }
     }
     // Remove icons
@@ -142,6 +153,17 @@ pub fn uninstall(yes: bool, dry_run: bool, restore_default: bool) -> Result<()>
             Err(e) => failures.push((dbus_service.clone(), e.to_string())),
         }
     }
+    // Stop the daemon and remove the systemd user unit
+    if systemd_unit.exists() {
+        let _ = crate::systemd::disable();
+        match fs::remove_file(&systemd_unit) {
+            Ok(_) => {
+                removed.push(systemd_unit.clone());
+                let _ = crate::systemd::systemctl(&["daemon-reload"]);
+            }
+            Err(e) => failures.push((systemd_unit.clone(), e.to_string())),
+        }
+    }
     // Remove config if approved
     if delete_config && cfg.exists() {
         if let Err(e) = fs::remove_file(&cfg) {